@@ -1,73 +1,668 @@
 use std::{
+    cell::UnsafeCell,
+    fmt,
+    hash::{Hash, Hasher},
+    mem::ManuallyDrop,
     ops::Deref,
     ptr::NonNull,
-    sync::atomic::{AtomicU32, Ordering},
+    sync::atomic::{fence, AtomicU16, AtomicU32, Ordering},
 };
 
-pub struct Arc<T> {
-    ptr: NonNull<ArcData<T>>,
+/// Atomic integer an Arc uses for its reference count
+/// Narrower counters save space on small targets at the cost of a lower reference limit
+pub trait RefCount {
+    /// Cloning past this many references panics instead of wrapping the counter
+    const MAX_REFS: usize;
+    /// Largest value the counter can hold, never reached by counting references
+    const MAX: usize;
+
+    fn new(value: usize) -> Self;
+    fn load(&self, order: Ordering) -> usize;
+    fn store(&self, value: usize, order: Ordering);
+    fn fetch_add(&self, value: usize, order: Ordering) -> usize;
+    fn fetch_sub(&self, value: usize, order: Ordering) -> usize;
+    fn compare_exchange(
+        &self,
+        current: usize,
+        new: usize,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<usize, usize>;
+}
+
+macro_rules! ref_count {
+    ($atomic:ty, $int:ty) => {
+        impl RefCount for $atomic {
+            const MAX_REFS: usize = (<$int>::MAX / 2) as usize;
+            const MAX: usize = <$int>::MAX as usize;
+
+            fn new(value: usize) -> Self {
+                <$atomic>::new(value as $int)
+            }
+
+            fn load(&self, order: Ordering) -> usize {
+                <$atomic>::load(self, order) as usize
+            }
+
+            fn store(&self, value: usize, order: Ordering) {
+                <$atomic>::store(self, value as $int, order)
+            }
+
+            fn fetch_add(&self, value: usize, order: Ordering) -> usize {
+                <$atomic>::fetch_add(self, value as $int, order) as usize
+            }
+
+            fn fetch_sub(&self, value: usize, order: Ordering) -> usize {
+                <$atomic>::fetch_sub(self, value as $int, order) as usize
+            }
+
+            fn compare_exchange(
+                &self,
+                current: usize,
+                new: usize,
+                success: Ordering,
+                failure: Ordering,
+            ) -> Result<usize, usize> {
+                <$atomic>::compare_exchange(self, current as $int, new as $int, success, failure)
+                    .map(|v| v as usize)
+                    .map_err(|v| v as usize)
+            }
+        }
+    };
+}
+
+ref_count!(AtomicU32, u32);
+ref_count!(AtomicU16, u16);
+
+/// Arc with a 16 bit reference count, for memory constrained targets
+pub type SmallArc<T> = Arc<T, AtomicU16>;
+
+pub struct Arc<T, C: RefCount = AtomicU32> {
+    ptr: NonNull<ArcData<T, C>>,
 }
 
 impl<T> Arc<T> {
     pub fn new(value: T) -> Self {
+        Self::with_counter(value)
+    }
+}
+
+impl<T, C: RefCount> Arc<T, C> {
+    /// Same as Arc::new, for any counter width e.g. SmallArc::with_counter(value)
+    pub fn with_counter(value: T) -> Self {
         Self {
             ptr: NonNull::from(Box::leak(Box::new(ArcData {
-                count: AtomicU32::new(0),
-                data: value,
+                count: C::new(1),
+                weak: C::new(1),
+                data: UnsafeCell::new(ManuallyDrop::new(value)),
             }))),
         }
     }
 
-    fn data(&self) -> &ArcData<T> {
+    fn data(&self) -> &ArcData<T, C> {
         unsafe { self.ptr.as_ref() }
     }
 
+    /// Drops the value once the last Arc is dropped, the allocation stays until the last Weak is gone too
+    /// Kept out of line so every drop site only inlines the decrement, like std's Arc
+    #[cold]
+    #[inline(never)]
+    fn drop_slow(&mut self) {
+        // No Arc is left, so nothing can reach the value anymore, and a Weak can't upgrade from a count of 0
+        unsafe { ManuallyDrop::drop(&mut *self.data().data.get()) }
+        // The Arcs' shared weak reference
+        drop(Weak { ptr: self.ptr })
+    }
+
     // Function invoked as Arc::get_mut() instead of a.get_mut()
     // It's advised to implement functions like so for types that implement Deref to avoid ambiguity with a similarly
     // defined method on the underlying T
     pub fn get_mut(arc: &mut Self) -> Option<&mut T> {
-        if arc.data().count.load(Ordering::Acquire) == 1 {
-            unsafe { Some(&mut arc.ptr.as_mut().data) }
-        } else {
-            None
+        // Lock the weak count, so no Weak can be created and upgraded while we check the strong one
+        // Fails while any Weak exists, since it could upgrade at any moment
+        if arc
+            .data()
+            .weak
+            .compare_exchange(1, C::MAX, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return None;
+        }
+        let is_unique = arc.data().count.load(Ordering::Acquire) == 1;
+        arc.data().weak.store(1, Ordering::Release);
+
+        if !is_unique {
+            return None;
+        }
+        unsafe { Some(&mut *arc.data().data.get()) }
+    }
+
+    /// The value, if `arc` is the only Arc to it, otherwise `arc` back
+    pub fn try_unwrap(arc: Self) -> Result<T, Self> {
+        // Taking the count to 0 makes any Weak's upgrade fail from here on
+        if arc
+            .data()
+            .count
+            .compare_exchange(1, 0, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return Err(arc);
+        }
+
+        let arc = ManuallyDrop::new(arc);
+        // Moved out exactly once: the count is 0 so no one else reaches it, and the allocation never drops it
+        let value = unsafe { ManuallyDrop::take(&mut *arc.data().data.get()) };
+        drop(Weak { ptr: arc.ptr });
+        Ok(value)
+    }
+
+    /// &mut to the value, cloning it into a new allocation first if anyone else can see it
+    /// Outstanding Weaks count as seeing it, they keep pointing at the old value
+    pub fn make_mut(arc: &mut Self) -> &mut T
+    where
+        T: Clone,
+    {
+        if Arc::get_mut(arc).is_none() {
+            *arc = Arc::with_counter((**arc).clone());
+        }
+        Arc::get_mut(arc).expect("a fresh Arc is unique")
+    }
+
+    /// Non-owning handle to the same allocation, which doesn't keep the value alive
+    pub fn downgrade(arc: &Self) -> Weak<T, C> {
+        let mut n = arc.data().weak.load(Ordering::Relaxed);
+        loop {
+            // Locked by get_mut, which only holds it for a moment
+            if n == C::MAX {
+                std::hint::spin_loop();
+                n = arc.data().weak.load(Ordering::Relaxed);
+                continue;
+            }
+            if n > C::MAX_REFS {
+                panic!("Arc reference count overflow");
+            }
+            match arc
+                .data()
+                .weak
+                .compare_exchange(n, n + 1, Ordering::Acquire, Ordering::Relaxed)
+            {
+                Ok(_) => return Weak { ptr: arc.ptr },
+                Err(e) => n = e,
+            }
+        }
+    }
+
+    /// Creates `n` new handles with a single atomic increment instead of `n` separate ones
+    pub fn clone_n(arc: &Self, n: usize) -> Vec<Self> {
+        // Checked up front so the increment itself can't wrap a narrow counter
+        if n > C::MAX_REFS {
+            panic!("Arc reference count overflow");
+        }
+        if arc.data().count.fetch_add(n, Ordering::Relaxed) > C::MAX_REFS - n {
+            arc.data().count.fetch_sub(n, Ordering::Relaxed);
+            panic!("Arc reference count overflow");
         }
+
+        (0..n).map(|_| Self { ptr: arc.ptr }).collect()
+    }
+
+    /// Number of live Arcs pointing at this allocation, including `arc`
+    /// Only a snapshot: other threads may clone or drop handles right after
+    pub fn strong_count(arc: &Self) -> usize {
+        arc.data().count.load(Ordering::Relaxed)
+    }
+
+    /// Whether both Arcs point at the same allocation
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+        a.ptr == b.ptr
     }
 }
 
-pub struct ArcData<T> {
-    count: AtomicU32,
-    data: T,
+pub struct ArcData<T, C: RefCount = AtomicU32> {
+    /// Number of live Arcs pointing at this allocation
+    count: C,
+    /// Number of live Weaks, plus one shared by all the Arcs while any are left
+    /// C::MAX while get_mut has it locked
+    weak: C,
+    /// Dropped when `count` hits 0, the allocation itself is freed when `weak` does
+    data: UnsafeCell<ManuallyDrop<T>>,
 }
 
-unsafe impl<T: Sync + Send> Sync for Arc<T> {}
-unsafe impl<T: Sync + Send> Send for Arc<T> {}
+unsafe impl<T: Sync + Send, C: RefCount> Sync for Arc<T, C> {}
+unsafe impl<T: Sync + Send, C: RefCount> Send for Arc<T, C> {}
 
-impl<T> Clone for Arc<T> {
+/// Arc::downgrade -> Weak, upgrades back into an Arc while the value is still alive
+/// Breaks reference cycles that would otherwise keep every Arc in them alive forever
+pub struct Weak<T, C: RefCount = AtomicU32> {
+    ptr: NonNull<ArcData<T, C>>,
+}
+
+unsafe impl<T: Sync + Send, C: RefCount> Sync for Weak<T, C> {}
+unsafe impl<T: Sync + Send, C: RefCount> Send for Weak<T, C> {}
+
+impl<T, C: RefCount> Weak<T, C> {
+    fn data(&self) -> &ArcData<T, C> {
+        unsafe { self.ptr.as_ref() }
+    }
+
+    /// None once every Arc was dropped, the value is gone then
+    pub fn upgrade(&self) -> Option<Arc<T, C>> {
+        let mut n = self.data().count.load(Ordering::Relaxed);
+        loop {
+            // A CAS instead of a fetch_add: incrementing from 0 would revive a value being dropped
+            if n == 0 {
+                return None;
+            }
+            if n >= C::MAX_REFS {
+                panic!("Arc reference count overflow");
+            }
+            match self
+                .data()
+                .count
+                .compare_exchange(n, n + 1, Ordering::Relaxed, Ordering::Relaxed)
+            {
+                Ok(_) => return Some(Arc { ptr: self.ptr }),
+                Err(e) => n = e,
+            }
+        }
+    }
+}
+
+impl<T, C: RefCount> Clone for Weak<T, C> {
     fn clone(&self) -> Self {
-        self.data().count.fetch_add(1, Ordering::Acquire);
+        if self.data().weak.fetch_add(1, Ordering::Relaxed) >= C::MAX_REFS {
+            self.data().weak.fetch_sub(1, Ordering::Relaxed);
+            panic!("Arc reference count overflow");
+        }
 
         Self { ptr: self.ptr }
     }
 }
 
-impl<T> Drop for Arc<T> {
+impl<T, C: RefCount> Drop for Weak<T, C> {
     fn drop(&mut self) {
-        let v = self.data().count.fetch_sub(1, Ordering::Acquire);
-        if v == 1 {
+        if self.data().weak.fetch_sub(1, Ordering::Release) == 1 {
+            // Every other handle's accesses happen before the free
+            fence(Ordering::Acquire);
             unsafe { drop(Box::from_raw(self.ptr.as_ptr())) }
         }
     }
 }
 
-impl<T> Deref for Arc<T> {
+impl<T, C: RefCount> Clone for Arc<T, C> {
+    fn clone(&self) -> Self {
+        // Relaxed: we already hold a reference, so there's nothing to synchronize with
+        if self.data().count.fetch_add(1, Ordering::Relaxed) >= C::MAX_REFS {
+            self.data().count.fetch_sub(1, Ordering::Relaxed);
+            panic!("Arc reference count overflow");
+        }
+
+        Self { ptr: self.ptr }
+    }
+}
+
+impl<T, C: RefCount> Drop for Arc<T, C> {
+    fn drop(&mut self) {
+        // Release so this handle's accesses to the value happen before whichever drop frees it,
+        // and that one acquires them all through the fence
+        if self.data().count.fetch_sub(1, Ordering::Release) == 1 {
+            fence(Ordering::Acquire);
+            self.drop_slow();
+        }
+    }
+}
+
+impl<T, C: RefCount> Deref for Arc<T, C> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
-        &self.data().data
+        // The value stays alive while this Arc is, and only get_mut hands out a &mut to it
+        unsafe { &*self.data().data.get() }
+    }
+}
+
+/// Compares the values, skipping the comparison for two handles to the same allocation
+/// Same allocation means equal for any Eq type, so that only speeds up self comparison.
+/// For a PartialEq only type it's observable: an Arc of NaN equals its clones, but not another Arc of NaN
+impl<T: PartialEq, C: RefCount> PartialEq for Arc<T, C> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(self, other) || **self == **other
+    }
+}
+
+impl<T: Eq, C: RefCount> Eq for Arc<T, C> {}
+
+/// Hashes the value, so equal Arcs hash the same whether or not they share an allocation
+impl<T: Hash, C: RefCount> Hash for Arc<T, C> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl<T: fmt::Debug, C: RefCount> fmt::Debug for Arc<T, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: fmt::Display, C: RefCount> fmt::Display for Arc<T, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<T, C: RefCount> From<T> for Arc<T, C> {
+    fn from(value: T) -> Self {
+        Self::with_counter(value)
+    }
+}
+
+impl<T: Default, C: RefCount> Default for Arc<T, C> {
+    fn default() -> Self {
+        Self::with_counter(T::default())
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{
+        cell::{Cell, RefCell},
+        collections::HashSet,
+        sync::{atomic::AtomicUsize, Mutex},
+        thread,
+    };
+
     use super::*;
+
+    /// Counts how many times it has been dropped
+    struct DropCounter<'a>(&'a AtomicUsize);
+
+    impl Drop for DropCounter<'_> {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn small_arc_clone_drop() {
+        let drops = AtomicUsize::new(0);
+        let mut a = SmallArc::with_counter(DropCounter(&drops));
+        assert!(Arc::get_mut(&mut a).is_some());
+
+        let b = a.clone();
+        assert!(Arc::get_mut(&mut a).is_none());
+
+        drop(b);
+        assert!(Arc::get_mut(&mut a).is_some());
+        assert_eq!(drops.load(Ordering::Relaxed), 0);
+
+        drop(a);
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn unique_until_cloned() {
+        let mut a = Arc::new(1);
+        assert_eq!(Arc::strong_count(&a), 1);
+
+        *Arc::get_mut(&mut a).unwrap() += 1;
+        assert_eq!(*a, 2);
+
+        let b = a.clone();
+        assert_eq!(Arc::strong_count(&a), 2);
+        assert!(Arc::get_mut(&mut a).is_none());
+
+        drop(b);
+        assert_eq!(Arc::strong_count(&a), 1);
+    }
+
+    #[test]
+    fn try_unwrap() {
+        let drops = AtomicUsize::new(0);
+        let a = Arc::new(DropCounter(&drops));
+        let b = a.clone();
+
+        let a = Arc::try_unwrap(a).err().unwrap();
+        drop(b);
+        let weak = Arc::downgrade(&a);
+
+        let value = Arc::try_unwrap(a).ok().unwrap();
+        assert!(weak.upgrade().is_none());
+        assert_eq!(drops.load(Ordering::Relaxed), 0);
+        drop(value);
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn make_mut() {
+        let mut a = Arc::new(vec![1]);
+        let a_ptr = a.ptr;
+        Arc::make_mut(&mut a).push(2);
+        // Unique, so no copy
+        assert_eq!(a.ptr, a_ptr);
+
+        let b = a.clone();
+        Arc::make_mut(&mut a).push(3);
+        assert_eq!(*a, [1, 2, 3]);
+        assert_eq!(*b, [1, 2]);
+        assert!(!Arc::ptr_eq(&a, &b));
+    }
+
+    #[test]
+    fn last_drop_frees() {
+        let drops = AtomicUsize::new(0);
+        let a = Arc::new(DropCounter(&drops));
+        let clones: Vec<_> = (0..4).map(|_| a.clone()).collect();
+        drop(clones);
+        assert_eq!(drops.load(Ordering::Relaxed), 0);
+
+        drop(a);
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    }
+
+    /// Singly linked list node, each node owning the next one through an Arc
+    struct Node<'a> {
+        next: Option<Arc<Node<'a>>>,
+        _drops: DropCounter<'a>,
+    }
+
+    impl Drop for Node<'_> {
+        /// Unlinks the chain iteratively, the default recursive drop overflows the stack on long chains
+        fn drop(&mut self) {
+            let mut next = self.next.take();
+            while let Some(mut arc) = next {
+                // Only the last handle may unlink, a shared tail is left to its other owners
+                next = match Arc::get_mut(&mut arc) {
+                    Some(node) => node.next.take(),
+                    None => None,
+                };
+            }
+        }
+    }
+
+    #[test]
+    fn drop_long_chain() {
+        const LEN: usize = 100_000;
+        let drops = AtomicUsize::new(0);
+
+        let mut head = None;
+        for _ in 0..LEN {
+            head = Some(Arc::new(Node {
+                next: head,
+                _drops: DropCounter(&drops),
+            }));
+        }
+
+        drop(head);
+        assert_eq!(drops.load(Ordering::Relaxed), LEN);
+    }
+
+    /// Counts how many times it has been compared
+    struct CmpCounter<'a>(&'a Cell<usize>);
+
+    impl PartialEq for CmpCounter<'_> {
+        fn eq(&self, _other: &Self) -> bool {
+            self.0.set(self.0.get() + 1);
+            true
+        }
+    }
+
+    #[test]
+    fn eq_same_allocation_short_circuits() {
+        let comparisons = Cell::new(0);
+        let a = Arc::new(CmpCounter(&comparisons));
+        let b = a.clone();
+        let c = Arc::new(CmpCounter(&comparisons));
+
+        assert!(a == b);
+        assert_eq!(comparisons.get(), 0);
+
+        assert!(a == c);
+        assert_eq!(comparisons.get(), 1);
+        assert!(!Arc::ptr_eq(&a, &c));
+    }
+
+    #[test]
+    fn clone_n() {
+        let drops = AtomicUsize::new(0);
+        let a = Arc::new(DropCounter(&drops));
+
+        let clones = Arc::clone_n(&a, 4);
+        assert_eq!(clones.len(), 4);
+        assert_eq!(Arc::strong_count(&a), 5);
+
+        drop(clones);
+        assert_eq!(Arc::strong_count(&a), 1);
+        drop(a);
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Arc reference count overflow")]
+    fn small_arc_clone_n_overflow() {
+        let a = SmallArc::with_counter(());
+        let _clones = Arc::clone_n(&a, <AtomicU16 as RefCount>::MAX_REFS);
+    }
+
+    #[test]
+    #[should_panic(expected = "Arc reference count overflow")]
+    fn small_arc_overflow() {
+        let a = SmallArc::with_counter(());
+        let _clones: Vec<_> = (0..<AtomicU16 as RefCount>::MAX_REFS)
+            .map(|_| a.clone())
+            .collect();
+    }
+
+    #[test]
+    fn upgrade_after_last_drop() {
+        let drops = AtomicUsize::new(0);
+        let a = Arc::new(DropCounter(&drops));
+        let weak = Arc::downgrade(&a);
+        let b = weak.upgrade().unwrap();
+        assert!(Arc::ptr_eq(&a, &b));
+
+        drop(a);
+        assert!(weak.upgrade().is_some());
+        drop(b);
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+        assert!(weak.upgrade().is_none());
+        assert!(weak.clone().upgrade().is_none());
+    }
+
+    #[test]
+    fn get_mut_with_weak() {
+        let mut a = Arc::new(0);
+        let weak = Arc::downgrade(&a);
+        assert!(Arc::get_mut(&mut a).is_none());
+
+        drop(weak);
+        *Arc::get_mut(&mut a).unwrap() += 1;
+        assert_eq!(*a, 1);
+    }
+
+    /// Node in a pair pointing at each other, weakly so the pair can be freed
+    struct Peer<'a> {
+        other: RefCell<Option<Weak<Peer<'a>>>>,
+        _drops: DropCounter<'a>,
+    }
+
+    #[test]
+    fn weak_cycle_doesnt_leak() {
+        let drops = AtomicUsize::new(0);
+        let a = Arc::new(Peer {
+            other: RefCell::new(None),
+            _drops: DropCounter(&drops),
+        });
+        let b = Arc::new(Peer {
+            other: RefCell::new(Some(Arc::downgrade(&a))),
+            _drops: DropCounter(&drops),
+        });
+        *a.other.borrow_mut() = Some(Arc::downgrade(&b));
+
+        let a_from_b = b.other.borrow().as_ref().unwrap().upgrade().unwrap();
+        assert!(Arc::ptr_eq(&a, &a_from_b));
+        drop(a_from_b);
+
+        drop(a);
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+        assert!(b.other.borrow().as_ref().unwrap().upgrade().is_none());
+        drop(b);
+        assert_eq!(drops.load(Ordering::Relaxed), 2);
+    }
+
+    /// Payload written through by every thread, so the final drop must see all of their writes
+    struct Tally<'a> {
+        hits: Mutex<Vec<usize>>,
+        drops: &'a AtomicUsize,
+    }
+
+    impl Drop for Tally<'_> {
+        fn drop(&mut self) {
+            let hits = self.hits.get_mut().unwrap();
+            assert_eq!(hits.len(), 8 * 1000);
+            self.drops.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Meant for --release too, where the orderings matter most
+    #[test]
+    fn concurrent_clone_drop() {
+        let drops = AtomicUsize::new(0);
+        let tally = Arc::new(Tally {
+            hits: Mutex::new(Vec::new()),
+            drops: &drops,
+        });
+
+        thread::scope(|s| {
+            for t in 0..8 {
+                let tally = tally.clone();
+                s.spawn(move || {
+                    for i in 0..1000 {
+                        let clone = tally.clone();
+                        clone.hits.lock().unwrap().push(t * 1000 + i);
+                        drop(clone);
+                    }
+                    // Whichever thread gets here last frees the payload
+                });
+            }
+            drop(tally);
+        });
+
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn forwards_to_value() {
+        let a = Arc::new(String::from("a"));
+        let mut set = HashSet::new();
+        set.insert(a.clone());
+        set.insert(Arc::from(String::from("a")));
+        set.insert(Arc::default());
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(&a));
+
+        assert_eq!(a, a.clone());
+        assert_eq!(format!("{a} {a:?}"), "a \"a\"");
+        assert_eq!(*SmallArc::<u8>::default(), 0);
+    }
 }
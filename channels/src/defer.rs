@@ -0,0 +1,77 @@
+/// Runs a closure when dropped, including while unwinding from a panic
+/// Keeps cleanup of half built or half torn down state next to the unsafe code that needs it
+pub struct Defer<F: FnMut()> {
+    f: F,
+    armed: bool,
+}
+
+/// Defers `f` until the returned guard goes out of scope
+pub fn guard<F: FnMut()>(f: F) -> Defer<F> {
+    Defer { f, armed: true }
+}
+
+impl<F: FnMut()> Defer<F> {
+    /// Disarms the guard, e.g. once construction succeeded and the state is owned by someone else
+    pub fn cancel(mut self) {
+        self.armed = false;
+    }
+}
+
+impl<F: FnMut()> Drop for Defer<F> {
+    fn drop(&mut self) {
+        if self.armed {
+            (self.f)()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        panic::{self, AssertUnwindSafe},
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use super::*;
+
+    struct DropCounter<'a>(&'a AtomicUsize);
+
+    impl Drop for DropCounter<'_> {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Hands out a raw allocation, like the channels do, panicking halfway if asked to
+    fn build<'a>(drops: &'a AtomicUsize, fail: bool) -> *mut DropCounter<'a> {
+        let raw = Box::into_raw(Box::new(DropCounter(drops)));
+        let cleanup = guard(|| drop(unsafe { Box::from_raw(raw) }));
+
+        if fail {
+            panic!("construction failed");
+        }
+
+        cleanup.cancel();
+        raw
+    }
+
+    #[test]
+    fn panic_mid_construction_frees() {
+        let drops = AtomicUsize::new(0);
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| build(&drops, true)));
+        assert!(result.is_err());
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn cancel_keeps_state() {
+        let drops = AtomicUsize::new(0);
+
+        let raw = build(&drops, false);
+        assert_eq!(drops.load(Ordering::Relaxed), 0);
+
+        drop(unsafe { Box::from_raw(raw) });
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,150 @@
+use std::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use atomic_wait::{wait, wake_all};
+
+use crate::oneshot::RecvError;
+
+/// Oneshot channel where every reader receives its own clone of the message
+struct Channel<T> {
+    /// 0 - Empty
+    /// 1 - Message sent
+    /// 2 - Writer dropped without sending
+    state: AtomicU32,
+    message: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// Readers clone the message concurrently through a shared reference, hence T: Sync
+unsafe impl<T: Send + Sync> Sync for Channel<T> {}
+
+impl<T> Drop for Channel<T> {
+    fn drop(&mut self) {
+        if *self.state.get_mut() == 1 {
+            unsafe { self.message.get_mut().assume_init_drop() }
+        }
+    }
+}
+
+pub fn fanout_oneshot<T: Clone>() -> (Writer<T>, ReaderFactory<T>) {
+    let channel = Arc::new(Channel {
+        state: AtomicU32::new(0),
+        message: UnsafeCell::new(MaybeUninit::uninit()),
+    });
+
+    (
+        Writer {
+            channel: Arc::clone(&channel),
+        },
+        ReaderFactory { channel },
+    )
+}
+
+pub struct Writer<T> {
+    channel: Arc<Channel<T>>,
+}
+
+impl<T> Writer<T> {
+    /// Publishes the message to every reader, past and future, and wakes those waiting
+    pub fn send(self, message: T) {
+        unsafe { (*self.channel.message.get()).write(message) };
+        self.channel.state.store(1, Ordering::Release);
+        wake_all(&self.channel.state)
+    }
+}
+
+impl<T> Drop for Writer<T> {
+    fn drop(&mut self) {
+        // Only if nothing was sent, send drops the writer too
+        if self
+            .channel
+            .state
+            .compare_exchange(0, 2, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+        {
+            wake_all(&self.channel.state)
+        }
+    }
+}
+
+/// Mints readers for the channel, readers created after the send get the message immediately
+#[derive(Clone)]
+pub struct ReaderFactory<T> {
+    channel: Arc<Channel<T>>,
+}
+
+impl<T> ReaderFactory<T> {
+    pub fn reader(&self) -> Reader<T> {
+        Reader {
+            channel: Arc::clone(&self.channel),
+        }
+    }
+}
+
+pub struct Reader<T> {
+    channel: Arc<Channel<T>>,
+}
+
+impl<T: Clone> Reader<T> {
+    /// Blocks until the message is sent, returning a clone of it
+    /// Err if the writer was dropped without sending
+    pub fn read(&self) -> Result<T, RecvError> {
+        loop {
+            match self.channel.state.load(Ordering::Acquire) {
+                0 => wait(&self.channel.state, 0),
+                1 => break,
+                _ => return Err(RecvError),
+            }
+        }
+
+        Ok(unsafe { (*self.channel.message.get()).assume_init_ref().clone() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fanout_oneshot;
+    use crate::oneshot::RecvError;
+    use std::{thread, time::Duration};
+
+    #[test]
+    fn every_reader_gets_a_clone() {
+        let (writer, readers) = fanout_oneshot::<String>();
+
+        let waiting: Vec<_> = (0..2)
+            .map(|_| {
+                let reader = readers.reader();
+                thread::spawn(move || reader.read())
+            })
+            .collect();
+
+        thread::sleep(Duration::from_millis(100));
+        writer.send("payload".to_string());
+
+        for t in waiting {
+            assert_eq!(t.join().unwrap().unwrap(), "payload");
+        }
+
+        // Registered after the send, returns straight away
+        assert_eq!(readers.reader().read().unwrap(), "payload");
+    }
+
+    #[test]
+    fn writer_dropped_without_sending() {
+        let (writer, readers) = fanout_oneshot::<String>();
+
+        let reader = readers.reader();
+        let waiting = thread::spawn(move || reader.read());
+
+        thread::sleep(Duration::from_millis(100));
+        drop(writer);
+
+        assert_eq!(waiting.join().unwrap(), Err(RecvError));
+        assert_eq!(readers.reader().read(), Err(RecvError));
+    }
+}
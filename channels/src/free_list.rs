@@ -0,0 +1,217 @@
+use std::{
+    mem::MaybeUninit,
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+};
+
+/// Allocation handed out by the free list
+/// repr(C) with the value first, so a pointer to the value is also a pointer to its block
+#[repr(C)]
+struct Block<T> {
+    value: MaybeUninit<T>,
+    /// Next free block, only meaningful while the block sits on the free list
+    next: *mut Block<T>,
+}
+
+/// Recycles fixed size allocations through a lock free stack of freed blocks,
+/// so steady state alloc/free pairs skip the global allocator
+///
+/// Frees push onto the stack from any thread. Pops are serialized by a flag instead of being fully lock free:
+/// with a single popper the head can only change through pushes, which rules out ABA without tagged pointers.
+/// A pop that finds the flag taken falls back to a fresh allocation rather than waiting
+pub struct FreeList<T> {
+    /// Top of the stack of free blocks
+    head: AtomicPtr<Block<T>>,
+    /// Set while a thread is popping
+    popping: AtomicBool,
+    /// Blocks taken from the global allocator so far
+    allocations: AtomicUsize,
+}
+
+unsafe impl<T: Send> Send for FreeList<T> {}
+unsafe impl<T: Send> Sync for FreeList<T> {}
+
+impl<T> FreeList<T> {
+    pub fn new() -> Self {
+        Self {
+            head: AtomicPtr::new(ptr::null_mut()),
+            popping: AtomicBool::new(false),
+            allocations: AtomicUsize::new(0),
+        }
+    }
+
+    /// Moves `value` into a recycled block if one is free, or a new allocation otherwise
+    pub fn alloc(&self, value: T) -> NonNull<T> {
+        let block = self.pop().unwrap_or_else(|| {
+            self.allocations.fetch_add(1, Ordering::Relaxed);
+            Box::into_raw(Box::new(Block {
+                value: MaybeUninit::uninit(),
+                next: ptr::null_mut(),
+            }))
+        });
+
+        unsafe {
+            (*block).value.write(value);
+            NonNull::new_unchecked(block.cast())
+        }
+    }
+
+    /// Drops the value and puts its block back on the free list
+    ///
+    /// # Safety
+    /// `ptr` has to come from alloc on this same free list, and can't be used after this call
+    pub unsafe fn free(&self, ptr: NonNull<T>) {
+        let block: *mut Block<T> = ptr.as_ptr().cast();
+        unsafe { (*block).value.assume_init_drop() };
+
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            unsafe { (*block).next = head };
+            // Release publishes the next pointer to whoever pops this block
+            match self
+                .head
+                .compare_exchange_weak(head, block, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => return,
+                Err(e) => head = e,
+            }
+        }
+    }
+
+    /// Number of blocks taken from the global allocator, recycled blocks aren't counted again
+    pub fn allocations(&self) -> usize {
+        self.allocations.load(Ordering::Relaxed)
+    }
+
+    /// Takes a block off the free list, None if it's empty or another thread is popping
+    fn pop(&self) -> Option<*mut Block<T>> {
+        if self.popping.swap(true, Ordering::Acquire) {
+            return None;
+        }
+
+        let mut head = self.head.load(Ordering::Acquire);
+        let block = loop {
+            if head.is_null() {
+                break None;
+            }
+            // Blocks are only deallocated when the free list is dropped, and nobody else can pop
+            // this one from under us, so reading next is fine even if the CAS then fails
+            let next = unsafe { (*head).next };
+            match self
+                .head
+                .compare_exchange_weak(head, next, Ordering::Acquire, Ordering::Acquire)
+            {
+                Ok(_) => break Some(head),
+                Err(e) => head = e,
+            }
+        };
+
+        self.popping.store(false, Ordering::Release);
+        block
+    }
+}
+
+impl<T> Default for FreeList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for FreeList<T> {
+    fn drop(&mut self) {
+        // Blocks still handed out belong to their owners, only the free ones are released here
+        let mut block = *self.head.get_mut();
+        while !block.is_null() {
+            let next = unsafe { (*block).next };
+            drop(unsafe { Box::from_raw(block) });
+            block = next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::atomic::AtomicUsize, thread};
+
+    use super::*;
+
+    #[test]
+    fn reuses_freed_blocks() {
+        let list = FreeList::new();
+
+        let a = list.alloc(1u64);
+        unsafe { list.free(a) };
+        let b = list.alloc(2u64);
+        assert_eq!(a, b);
+        assert_eq!(unsafe { *b.as_ref() }, 2);
+
+        unsafe { list.free(b) };
+        assert_eq!(list.allocations(), 1);
+    }
+
+    #[test]
+    fn free_drops_value() {
+        struct DropCounter<'a>(&'a AtomicUsize);
+
+        impl Drop for DropCounter<'_> {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let drops = AtomicUsize::new(0);
+        let list = FreeList::new();
+        let ptr = list.alloc(DropCounter(&drops));
+        unsafe { list.free(ptr) };
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+
+        // Dropping the list releases the free block without dropping its value again
+        drop(list);
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    }
+
+    /// Enqueue/dequeue pattern of a queue that never holds more than `depth` items
+    /// Returns how many blocks came from the global allocator, against 10000 for a Box per item
+    fn steady_state_allocations(depth: usize) -> usize {
+        let list = FreeList::new();
+        let mut queued = Vec::new();
+        for i in 0..10000 {
+            queued.push(list.alloc(i));
+            if queued.len() == depth {
+                for ptr in queued.drain(..) {
+                    unsafe { list.free(ptr) };
+                }
+            }
+        }
+        list.allocations()
+    }
+
+    #[test]
+    fn steady_state_reuse() {
+        assert_eq!(steady_state_allocations(1), 1);
+        assert_eq!(steady_state_allocations(16), 16);
+    }
+
+    #[test]
+    fn concurrent_alloc_free() {
+        let list = FreeList::new();
+
+        thread::scope(|s| {
+            for t in 0..4 {
+                let list = &list;
+                s.spawn(move || {
+                    for i in 0..10000 {
+                        let ptr = list.alloc((t, i));
+                        assert_eq!(unsafe { *ptr.as_ref() }, (t, i));
+                        unsafe { list.free(ptr) };
+                    }
+                });
+            }
+        });
+
+        // Every block ended up back on the free list, so the next alloc is a recycled one
+        let before = list.allocations();
+        unsafe { list.free(list.alloc((0, 0))) };
+        assert_eq!(list.allocations(), before);
+    }
+}
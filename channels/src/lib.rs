@@ -1 +1,11 @@
+pub mod defer;
+pub mod fanout;
+pub mod free_list;
+pub mod mpsc;
 pub mod oneshot;
+pub mod rendezvous;
+pub mod rpc;
+pub mod scoped_oneshot;
+pub mod thread_pool;
+pub mod unbounded;
+
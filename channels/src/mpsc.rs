@@ -0,0 +1,409 @@
+use std::{
+    cell::{Cell, UnsafeCell},
+    marker::PhantomData,
+    mem::MaybeUninit,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use atomic_wait::{wait, wake_all, wake_one};
+
+use crate::{defer::guard, unbounded::SendError};
+
+struct Slot<T> {
+    /// What the slot is ready for: 2 * pos when free for a sender at position pos,
+    /// 2 * pos + 1 once filled at pos and ready for the receiver
+    /// Doubled so the two never collide, with capacity 1 "filled at pos" would otherwise equal "free at pos + 1"
+    seq: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// Fixed size ring of slots (Vyukov's bounded queue): senders claim a position by CASing the tail,
+/// each slot's sequence number tells whether it's free for that position or still holds an older value
+struct Channel<T> {
+    slots: Box<[Slot<T>]>,
+    /// Next position a sender claims
+    tail: AtomicUsize,
+    /// Next position the receiver takes, only touched by the receiver
+    head: AtomicUsize,
+    /// Bumped on every send and on disconnect, the receiver sleeps on it while the ring is empty
+    not_empty: AtomicU32,
+    /// Bumped on every receive and when the receiver goes, senders sleep on it while the ring is full
+    not_full: AtomicU32,
+    senders: AtomicUsize,
+    receiver_alive: AtomicBool,
+}
+
+unsafe impl<T: Send> Send for Channel<T> {}
+unsafe impl<T: Send> Sync for Channel<T> {}
+
+impl<T> Channel<T> {
+    /// Queues `value` if there's room, handing it back if the ring is full
+    fn try_push(&self, value: T) -> Result<(), T> {
+        let capacity = self.slots.len();
+        let mut pos = self.tail.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos % capacity];
+            // Acquire pairs with the receiver's Release, it's done reading the old value
+            let seq = slot.seq.load(Ordering::Acquire);
+            let free = pos.wrapping_mul(2);
+
+            if seq == free {
+                match self.tail.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // Claimed, no other sender gets this position
+                        unsafe { (*slot.value.get()).write(value) };
+                        slot.seq.store(free.wrapping_add(1), Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(actual) => pos = actual,
+                }
+            } else if (seq as isize).wrapping_sub(free as isize) < 0 {
+                // Still holds the value from a lap ago, the receiver hasn't caught up
+                return Err(value);
+            } else {
+                // Another sender claimed pos and already filled it, catch up
+                pos = self.tail.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Takes the oldest value, if any
+    ///
+    /// # Safety
+    /// Only the receiver may pop
+    unsafe fn try_pop(&self) -> Option<T> {
+        let pos = self.head.load(Ordering::Relaxed);
+        let slot = &self.slots[pos % self.slots.len()];
+        // Acquire pairs with the sender's Release, making the value visible
+        if slot.seq.load(Ordering::Acquire) != pos.wrapping_mul(2).wrapping_add(1) {
+            return None;
+        }
+
+        let value = unsafe { (*slot.value.get()).assume_init_read() };
+        // Free for the sender that comes around on the next lap
+        let next_lap = pos.wrapping_add(self.slots.len());
+        slot.seq.store(next_lap.wrapping_mul(2), Ordering::Release);
+        self.head.store(pos.wrapping_add(1), Ordering::Relaxed);
+        Some(value)
+    }
+
+    /// Claimed positions not yet received, a snapshot that may be stale by the time it's returned
+    /// Counts a value whose sender claimed its slot but is still writing it
+    fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Relaxed);
+        // Loaded apart, a receive in between can put head past the tail seen
+        (tail.wrapping_sub(head) as isize).clamp(0, self.slots.len() as isize) as usize
+    }
+}
+
+impl<T> Drop for Channel<T> {
+    fn drop(&mut self) {
+        // Both halves are gone, so this is the only popper left
+        while let Some(value) = unsafe { self.try_pop() } {
+            // Should dropping it panic, the values behind it are still dropped while unwinding
+            let rest = guard(|| while unsafe { self.try_pop() }.is_some() {});
+            drop(value);
+            rest.cancel();
+        }
+    }
+}
+
+/// Channel holding at most `capacity` values: sends block while it's full, the receiver blocks while it's empty
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(
+        capacity > 0,
+        "capacity must be at least 1, see rendezvous for a zero capacity channel"
+    );
+
+    let channel = Arc::new(Channel {
+        slots: (0..capacity)
+            .map(|i| Slot {
+                seq: AtomicUsize::new(i * 2),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            })
+            .collect(),
+        tail: AtomicUsize::new(0),
+        head: AtomicUsize::new(0),
+        not_empty: AtomicU32::new(0),
+        not_full: AtomicU32::new(0),
+        senders: AtomicUsize::new(1),
+        receiver_alive: AtomicBool::new(true),
+    });
+
+    (
+        Sender {
+            channel: Arc::clone(&channel),
+        },
+        Receiver {
+            channel,
+            _not_sync: PhantomData,
+        },
+    )
+}
+
+pub struct Sender<T> {
+    channel: Arc<Channel<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Queues `value`, blocking while the channel is full, fails only if the receiver was dropped
+    pub fn send(&self, mut value: T) -> Result<(), SendError<T>> {
+        loop {
+            // Loaded before trying, so a receive landing in between makes the wait return
+            let not_full = self.channel.not_full.load(Ordering::Acquire);
+            if !self.channel.receiver_alive.load(Ordering::Relaxed) {
+                return Err(SendError(value));
+            }
+
+            match self.channel.try_push(value) {
+                Ok(()) => {
+                    self.channel.not_empty.fetch_add(1, Ordering::Release);
+                    wake_one(&self.channel.not_empty);
+                    return Ok(());
+                }
+                Err(v) => value = v,
+            }
+
+            wait(&self.channel.not_full, not_full)
+        }
+    }
+
+    /// Whether a send would block right now, approximate while other threads send or receive
+    pub fn is_full(&self) -> bool {
+        self.channel.len() == self.channel.slots.len()
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.channel.senders.fetch_add(1, Ordering::Relaxed);
+        Self {
+            channel: Arc::clone(&self.channel),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        // Last sender gone: wake the receiver so it can notice the disconnect
+        if self.channel.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.channel.not_empty.fetch_add(1, Ordering::Release);
+            wake_one(&self.channel.not_empty);
+        }
+    }
+}
+
+pub struct Receiver<T> {
+    channel: Arc<Channel<T>>,
+    /// Single consumer: the receiver can move between threads, but not be shared by them
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl<T> Receiver<T> {
+    /// Blocks until a value arrives, None once every sender is gone and the channel is drained
+    pub fn recv(&self) -> Option<T> {
+        loop {
+            let not_empty = self.channel.not_empty.load(Ordering::Acquire);
+
+            if let Some(value) = unsafe { self.channel.try_pop() } {
+                // A slot just freed up for a blocked sender
+                self.channel.not_full.fetch_add(1, Ordering::Release);
+                wake_one(&self.channel.not_full);
+                return Some(value);
+            }
+            if self.channel.senders.load(Ordering::Acquire) == 0 {
+                // A last send may have landed after the pop above
+                return unsafe { self.channel.try_pop() };
+            }
+
+            wait(&self.channel.not_empty, not_empty)
+        }
+    }
+
+    /// Values waiting to be received, approximate while senders are sending
+    pub fn len(&self) -> usize {
+        self.channel.len()
+    }
+
+    /// Whether a recv would block right now, approximate while senders are sending
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.channel.receiver_alive.store(false, Ordering::Relaxed);
+        // Senders blocked on a full channel would never be woken otherwise
+        self.channel.not_full.fetch_add(1, Ordering::Release);
+        wake_all(&self.channel.not_full);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        panic::{self, AssertUnwindSafe},
+        thread,
+        time::Duration,
+    };
+
+    use super::*;
+
+    #[test]
+    fn producers_feed_one_consumer() {
+        let (sender, receiver) = channel(4);
+
+        let producers: Vec<_> = (0..4)
+            .map(|p| {
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    for i in 0..1000 {
+                        sender.send(p * 1000 + i).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(sender);
+
+        let mut received = Vec::new();
+        while let Some(value) = receiver.recv() {
+            received.push(value);
+        }
+        for producer in producers {
+            producer.join().unwrap();
+        }
+
+        received.sort();
+        assert_eq!(received, (0..4000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn send_blocks_while_full() {
+        let (sender, receiver) = channel(2);
+        sender.send(1).unwrap();
+        sender.send(2).unwrap();
+
+        let t = thread::spawn(move || {
+            sender.send(3).unwrap();
+        });
+        thread::sleep(Duration::from_millis(50));
+        assert!(!t.is_finished());
+
+        assert_eq!(receiver.recv(), Some(1));
+        t.join().unwrap();
+        assert_eq!(receiver.recv(), Some(2));
+        assert_eq!(receiver.recv(), Some(3));
+        // The sender moved into the thread and is gone now
+        assert_eq!(receiver.recv(), None);
+    }
+
+    #[test]
+    fn receiver_dropped_unblocks_senders() {
+        let (sender, receiver) = channel(1);
+        sender.send(1).unwrap();
+
+        let t = thread::spawn(move || sender.send(2));
+        thread::sleep(Duration::from_millis(50));
+        drop(receiver);
+        assert_eq!(t.join().unwrap(), Err(SendError(2)));
+    }
+
+    #[test]
+    fn len_tracks_sends_and_receives() {
+        let (sender, receiver) = channel(3);
+        assert!(receiver.is_empty());
+
+        for i in 0..3 {
+            assert!(!sender.is_full());
+            sender.send(i).unwrap();
+            assert_eq!(receiver.len(), i + 1);
+        }
+        assert!(sender.is_full());
+
+        // Around the ring a few times
+        for i in 3..10 {
+            assert_eq!(receiver.recv(), Some(i - 3));
+            assert_eq!(receiver.len(), 2);
+            sender.send(i).unwrap();
+            assert!(sender.is_full());
+        }
+
+        for left in (0..3).rev() {
+            receiver.recv().unwrap();
+            assert_eq!(receiver.len(), left);
+        }
+        assert!(receiver.is_empty());
+    }
+
+    #[test]
+    fn len_within_capacity() {
+        let (sender, receiver) = channel(4);
+
+        let producers: Vec<_> = (0..3)
+            .map(|_| {
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    for i in 0..1000 {
+                        assert!(sender.channel.len() <= 4);
+                        sender.send(i).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(sender);
+
+        while receiver.recv().is_some() {
+            assert!(receiver.len() <= 4);
+        }
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        assert!(receiver.is_empty());
+    }
+
+    #[test]
+    fn drops_unreceived() {
+        let (sender, receiver) = channel(4);
+        let value = Arc::new(());
+        sender.send(Arc::clone(&value)).unwrap();
+        sender.send(Arc::clone(&value)).unwrap();
+
+        drop((sender, receiver));
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn panicking_drop_frees_the_rest() {
+        #[derive(Debug)]
+        struct PanicOnDrop(#[allow(dead_code)] Arc<()>);
+
+        impl Drop for PanicOnDrop {
+            fn drop(&mut self) {
+                if !thread::panicking() {
+                    panic!("drop failed");
+                }
+            }
+        }
+
+        let (sender, receiver) = channel(4);
+        let value = Arc::new(());
+        for _ in 0..3 {
+            sender.send(PanicOnDrop(Arc::clone(&value))).unwrap();
+        }
+
+        let halves = AssertUnwindSafe((sender, receiver));
+        let result = panic::catch_unwind(move || drop(halves));
+        assert!(result.is_err());
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+}
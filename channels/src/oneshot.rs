@@ -1,25 +1,188 @@
 use std::{
     cell::UnsafeCell,
+    fmt,
     mem::MaybeUninit,
     sync::{
-        atomic::{AtomicU32, Ordering},
-        Arc,
+        atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+        Arc, Weak,
     },
+    time::Instant,
 };
 
 use atomic_wait::{wait, wake_all};
+use locks::{mutex::Mutex, wait::wait_timeout};
+
+/// No message to read: the writer was dropped without sending, or the message was already taken
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvTimeoutError {
+    /// The deadline passed before a message was sent
+    Timeout,
+    /// No message to read: the writer was dropped without sending, or the message was already taken
+    Disconnected,
+}
+
+/// Lifecycle of the message, stored as a u32 in Channel::state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+enum State {
+    /// Nothing sent yet
+    Empty = 0,
+    /// Message sent, waiting to be read
+    Ready = 1,
+    /// A reader is moving the message out
+    Reading = 2,
+    /// Message moved out, nothing left to read
+    Read = 3,
+    /// Writer dropped without sending, nothing will ever be read
+    Closed = 4,
+}
+
+impl TryFrom<u32> for State {
+    type Error = u32;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(State::Empty),
+            1 => Ok(State::Ready),
+            2 => Ok(State::Reading),
+            3 => Ok(State::Read),
+            4 => Ok(State::Closed),
+            _ => Err(value),
+        }
+    }
+}
 
 struct Channel<T> {
     state: AtomicU32,
     message: UnsafeCell<MaybeUninit<T>>,
+    /// Reader and Writer still alive, the last one to drop hands a pooled channel back
+    halves: AtomicU32,
+    /// Set when the Reader drops, so the Writer can tell nobody is listening anymore
+    reader_dropped: AtomicBool,
+    /// Free list of the OneshotPool this channel came from, dangling for a plain channel()
+    pool: Weak<FreeChannels<T>>,
+}
+
+/// Both halves use the channel at the same time through an Arc, so it has to be Sync for them to be Send
+/// T only needs to be Send: the message is moved from the writer's thread to the reader's and never
+/// shared by reference, the state transitions make sure only one side touches the cell at a time.
+/// Send is derived, UnsafeCell<MaybeUninit<T>> is already Send when T is
+unsafe impl<T: Send> Sync for Channel<T> {}
+
+impl<T> Channel<T> {
+    fn new() -> Self {
+        Self::pooled(Weak::new())
+    }
+
+    fn pooled(pool: Weak<FreeChannels<T>>) -> Self {
+        Self {
+            state: AtomicU32::new(State::Empty as u32),
+            message: UnsafeCell::new(MaybeUninit::uninit()),
+            halves: AtomicU32::new(2),
+            reader_dropped: AtomicBool::new(false),
+            pool,
+        }
+    }
+
+    /// Called as each half drops, the last one recycles the channel if it came from a pool
+    /// Only a channel that's done (message read, or never sent) is reset, one still holding a message isn't reused
+    fn release(self: &Arc<Self>) {
+        // AcqRel so the last half sees everything the other one did to the channel
+        if self.halves.fetch_sub(1, Ordering::AcqRel) != 1 {
+            return;
+        }
+        let Some(pool) = self.pool.upgrade() else {
+            return;
+        };
+
+        let state = State::try_from(self.state.load(Ordering::Relaxed));
+        if matches!(state, Ok(State::Read | State::Closed)) {
+            // Nobody else can reach the channel until it's handed out again
+            self.state.store(State::Empty as u32, Ordering::Relaxed);
+            self.halves.store(2, Ordering::Relaxed);
+            self.reader_dropped.store(false, Ordering::Relaxed);
+            pool.with(|free| free.push(Arc::clone(self)));
+        }
+    }
+
+    /// Moves the state from `from` to `to`, returning the actual state if it wasn't `from`
+    fn transition(&self, from: State, to: State, success: Ordering) -> Result<(), State> {
+        self.state
+            .compare_exchange(from as u32, to as u32, success, Ordering::Relaxed)
+            .map(|_| ())
+            .map_err(|s| State::try_from(s).expect("corrupt oneshot state"))
+    }
+
+    /// Empty -> Ready, a message can only be sent once
+    /// Release, pairing with the Acquire in try_begin_read, so a reader that sees Ready also sees the message
+    fn try_mark_ready(&self) -> Result<(), State> {
+        self.transition(State::Empty, State::Ready, Ordering::Release)
+    }
+
+    /// Ready -> Reading, only one reader gets to move the message out
+    fn try_begin_read(&self) -> Result<(), State> {
+        self.transition(State::Ready, State::Reading, Ordering::Acquire)
+    }
+
+    /// Reading -> Read, once the message has been moved out
+    fn mark_read(&self) -> Result<(), State> {
+        self.transition(State::Reading, State::Read, Ordering::Release)
+    }
+
+    /// Whether the message was moved out, only a hint under concurrency
+    fn was_read(&self) -> bool {
+        self.state.load(Ordering::Relaxed) == State::Read as u32
+    }
+
+    /// Empty -> Closed, when the writer goes away without sending
+    fn try_close(&self) -> Result<(), State> {
+        self.transition(State::Empty, State::Closed, Ordering::Relaxed)
+    }
+}
+
+impl<T> Drop for Channel<T> {
+    /// Runs once both halves are gone, a message that was sent but never read is dropped here
+    fn drop(&mut self) {
+        if *self.state.get_mut() == State::Ready as u32 {
+            unsafe { self.message.get_mut().assume_init_drop() }
+        }
+    }
 }
 
-unsafe impl<T: Send> Send for Channel<T> {}
+/// Creates a channel for sending a single message
+///
+/// Reader and Writer can move to other threads as long as the message can:
+///
+/// ```
+/// use std::thread;
+///
+/// let (reader, writer) = channels::oneshot::channel();
+/// thread::spawn(move || writer.send(String::from("hi")).unwrap());
+/// assert_eq!(thread::spawn(move || reader.read()).join().unwrap(), Ok(String::from("hi")));
+/// ```
+///
+/// A message that isn't Send, like an Rc, keeps both halves on the thread that created them:
+///
+/// ```compile_fail,E0277
+/// use std::{rc::Rc, thread};
+///
+/// let (reader, writer) = channels::oneshot::channel();
+/// thread::spawn(move || writer.send(Rc::new(1)));
+/// reader.read();
+/// ```
+///
+/// ```compile_fail,E0277
+/// use std::{rc::Rc, thread};
+///
+/// let (reader, writer) = channels::oneshot::channel::<Rc<i32>>();
+/// thread::spawn(move || reader.read());
+/// writer.send(Rc::new(1));
+/// ```
 pub fn channel<T>() -> (Reader<T>, Writer<T>) {
-    let channel = Arc::new(Channel {
-        state: AtomicU32::new(0),
-        message: UnsafeCell::new(MaybeUninit::uninit()),
-    });
+    let channel = Arc::new(Channel::new());
 
     (
         Reader {
@@ -31,55 +194,198 @@ pub fn channel<T>() -> (Reader<T>, Writer<T>) {
     )
 }
 
-struct Reader<T> {
+pub struct Reader<T> {
     channel: Arc<Channel<T>>,
 }
 
-unsafe impl<T: Send> Send for Reader<T> {}
-
 impl<T: Send> Reader<T> {
-    fn read(&self) -> T {
-        // Check if state == 1 -> Ready for reading
-        while self.channel.state.load(Ordering::Acquire) != 1 {
-            // Wait until message has been sent i.e state -> 1
-            wait(&self.channel.state, 0)
+    /// Blocks until the message arrives
+    /// Err if the writer was dropped without sending, or the message was already taken, e.g. by try_recv
+    pub fn read(&self) -> Result<T, RecvError> {
+        loop {
+            match self.channel.try_begin_read() {
+                Ok(()) => return Ok(self.take()),
+                // Wait until message has been sent i.e Empty -> Ready
+                Err(State::Empty) => wait(&self.channel.state, State::Empty as u32),
+                Err(State::Closed | State::Read) => return Err(RecvError),
+                // Reader is Sync, another thread is moving the message out and is about to mark it Read
+                Err(State::Reading) => std::hint::spin_loop(),
+                Err(State::Ready) => unreachable!("the CAS only fails on another state"),
+            }
         }
+    }
 
-        unsafe { (*self.channel.message.get()).assume_init_read() }
+    /// Same as read, named like the other channels' receive
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.read()
+    }
+
+    /// Takes the message if it has arrived, without blocking
+    /// None before the send, and after the message was taken once
+    pub fn try_recv(&self) -> Option<T> {
+        self.channel.try_begin_read().ok().map(|()| self.take())
+    }
+
+    /// Like read, but gives up once `deadline` has passed
+    pub fn recv_deadline(&self, deadline: Instant) -> Result<T, RecvTimeoutError> {
+        loop {
+            match self.channel.try_begin_read() {
+                Ok(()) => return Ok(self.take()),
+                Err(State::Empty) => {
+                    // Sleep only for what's left until the deadline, recomputed after every wake up
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(RecvTimeoutError::Timeout);
+                    }
+                    wait_timeout(&self.channel.state, State::Empty as u32, deadline - now)
+                }
+                Err(State::Closed | State::Read) => return Err(RecvTimeoutError::Disconnected),
+                Err(State::Reading) => std::hint::spin_loop(),
+                Err(State::Ready) => unreachable!("the CAS only fails on another state"),
+            }
+        }
+    }
+
+    /// Whether the message was already consumed, e.g. to know a retry would be a duplicate
+    pub fn was_read(&self) -> bool {
+        self.channel.was_read()
+    }
+
+    /// Moves the message out, only after winning try_begin_read
+    fn take(&self) -> T {
+        let message = unsafe { (*self.channel.message.get()).assume_init_read() };
+        self.channel
+            .mark_read()
+            .expect("only the reader that began the read can finish it");
+        message
     }
 }
 
 impl<T> Drop for Reader<T> {
-    fn drop(&mut self) {}
+    fn drop(&mut self) {
+        self.channel.reader_dropped.store(true, Ordering::Relaxed);
+        self.channel.release()
+    }
 }
 
-struct Writer<T> {
+pub struct Writer<T> {
     channel: Arc<Channel<T>>,
 }
-unsafe impl<T: Send> Send for Writer<T> {}
+
+impl<T> Writer<T> {
+    /// Whether the Reader was dropped, i.e. a message sent now would never be read
+    pub fn is_closed(&self) -> bool {
+        self.channel.reader_dropped.load(Ordering::Relaxed)
+    }
+}
+
 impl<T: Send> Writer<T> {
-    fn send(self, message: T) {
-        if let Err(e) =
-            self.channel
-                .state
-                .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
-        {
-            panic!("Invalid state, cannot send message: state is {e}")
+    /// Sends the message, consuming the writer so a oneshot channel can only ever be sent on once
+    ///
+    /// ```
+    /// let (reader, writer) = channels::oneshot::channel();
+    /// writer.send(1).unwrap();
+    /// assert_eq!(reader.read(), Ok(1));
+    /// ```
+    ///
+    /// Sending a second time is a use after move, and must stay a compile error
+    /// if `send` is ever refactored, e.g. to take `&self`:
+    ///
+    /// ```compile_fail,E0382
+    /// let (_reader, writer) = channels::oneshot::channel();
+    /// writer.send(1).unwrap();
+    /// writer.send(2).unwrap();
+    /// ```
+    ///
+    /// Because of that the Err case can't happen short of a corrupted channel state, it hands
+    /// the writer and message back instead of panicking so the caller decides what to do
+    ///
+    /// The message is written before the state flips to Ready with a Release, so it's visible to the reader
+    /// no matter what the sending thread does next, e.g. exit right away. No extra fence is needed
+    pub fn send(self, message: T) -> Result<(), (Self, T)> {
+        // Only this Writer moves the state out of Empty, and send consumes it, so nobody else touches the cell yet
+        unsafe { (*self.channel.message.get()).write(message) };
+        if self.channel.try_mark_ready().is_err() {
+            // Never became Ready, so no reader touched the cell and the message is still ours
+            let message = unsafe { (*self.channel.message.get()).assume_init_read() };
+            return Err((self, message));
         }
 
-        unsafe { (*self.channel.message.get()).write(message) };
         // Wake potential waiting reader(s)
-        wake_all(&self.channel.state)
+        wake_all(&self.channel.state);
+        Ok(())
     }
 }
+
+impl<T> fmt::Debug for Writer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Writer").finish_non_exhaustive()
+    }
+}
+
 impl<T> Drop for Writer<T> {
-    fn drop(&mut self) {}
+    fn drop(&mut self) {
+        // Fails if a message was sent, otherwise tell the reader none is coming
+        if self.channel.try_close().is_ok() {
+            wake_all(&self.channel.state)
+        }
+        self.channel.release()
+    }
+}
+
+type FreeChannels<T> = Mutex<Vec<Arc<Channel<T>>>>;
+
+/// Hands out oneshot channels, reusing ones whose message was read instead of allocating a new one every time
+/// A channel goes back to the pool once both its Reader and Writer are dropped
+pub struct OneshotPool<T> {
+    free: Arc<FreeChannels<T>>,
+    /// Channels allocated so far, recycled ones aren't counted again
+    allocations: AtomicUsize,
+}
+
+impl<T> OneshotPool<T> {
+    pub fn new() -> Self {
+        Self {
+            free: Arc::new(Mutex::new(Vec::new())),
+            allocations: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn acquire(&self) -> (Reader<T>, Writer<T>) {
+        let recycled = self.free.with(|free| free.pop());
+        let channel = recycled.unwrap_or_else(|| {
+            self.allocations.fetch_add(1, Ordering::Relaxed);
+            Arc::new(Channel::pooled(Arc::downgrade(&self.free)))
+        });
+
+        (
+            Reader {
+                channel: Arc::clone(&channel),
+            },
+            Writer { channel },
+        )
+    }
+
+    /// Number of channels allocated by this pool
+    pub fn allocations(&self) -> usize {
+        self.allocations.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Default for OneshotPool<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::channel;
-    use std::{thread, time::Duration};
+    use super::{channel, Channel, OneshotPool, RecvError, RecvTimeoutError, State};
+    use std::{
+        sync::Arc,
+        thread,
+        time::{Duration, Instant},
+    };
 
     #[test]
     fn read_write() {
@@ -87,7 +393,7 @@ mod tests {
 
         let reader_thread = thread::spawn(move || {
             println!("Reader waiting to receive message");
-            let message = reader.read();
+            let message = reader.read().unwrap();
             assert_eq!(message, "It's working".to_owned());
             println!("message is: {message}");
         });
@@ -95,10 +401,229 @@ mod tests {
         thread::sleep(Duration::from_millis(500));
 
         let writer_thread = thread::spawn(move || {
-            writer.send("It's working".to_string());
+            writer.send("It's working".to_string()).unwrap();
         });
 
         reader_thread.join().unwrap();
         writer_thread.join().unwrap();
     }
+
+    #[test]
+    fn message_visible_after_writer_thread_exits() {
+        for i in 0..1000 {
+            let (reader, writer) = channel::<Vec<u32>>();
+
+            // The writer's thread ends right after sending, the reader may or may not already be waiting
+            let writer_thread = thread::spawn(move || writer.send(vec![i; 16]).unwrap());
+            let reader_thread = thread::spawn(move || reader.read());
+
+            assert_eq!(reader_thread.join().unwrap(), Ok(vec![i; 16]));
+            writer_thread.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn recv_deadline_timeout() {
+        let (reader, _writer) = channel::<u32>();
+
+        let start = Instant::now();
+        let result = reader.recv_deadline(start + Duration::from_millis(100));
+        let elapsed = start.elapsed();
+
+        assert_eq!(result, Err(RecvTimeoutError::Timeout));
+        assert!(elapsed >= Duration::from_millis(100));
+        assert!(elapsed < Duration::from_millis(500), "took {elapsed:?}");
+    }
+
+    #[test]
+    fn recv_deadline_before_timeout() {
+        let (reader, writer) = channel::<u32>();
+
+        let writer_thread = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            writer.send(7).unwrap();
+        });
+
+        let result = reader.recv_deadline(Instant::now() + Duration::from_secs(5));
+        assert_eq!(result, Ok(7));
+        writer_thread.join().unwrap();
+    }
+
+    #[test]
+    fn writer_dropped_without_sending() {
+        let (reader, writer) = channel::<u32>();
+
+        let reader_thread = thread::spawn(move || reader.read());
+        thread::sleep(Duration::from_millis(50));
+        drop(writer);
+        assert_eq!(reader_thread.join().unwrap(), Err(RecvError));
+
+        let (reader, writer) = channel::<u32>();
+        let writer_thread = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            drop(writer);
+        });
+
+        assert_eq!(reader.recv(), Err(RecvError));
+        writer_thread.join().unwrap();
+
+        let (reader, writer) = channel::<u32>();
+        drop(writer);
+        let result = reader.recv_deadline(Instant::now() + Duration::from_secs(5));
+        assert_eq!(result, Err(RecvTimeoutError::Disconnected));
+    }
+
+    #[test]
+    fn halves_observe_disconnect() {
+        let (reader, writer) = channel::<u32>();
+        assert!(!writer.is_closed());
+        drop(reader);
+        assert!(writer.is_closed());
+
+        let (reader, writer) = channel::<u32>();
+        drop(writer);
+        assert_eq!(reader.recv(), Err(RecvError));
+    }
+
+    #[test]
+    fn unread_message_dropped_with_channel() {
+        let (reader, writer) = channel();
+        let message = Arc::new(());
+        writer.send(Arc::clone(&message)).unwrap();
+        assert_eq!(Arc::strong_count(&message), 2);
+
+        drop(reader);
+        assert_eq!(Arc::strong_count(&message), 1);
+    }
+
+    #[test]
+    fn send_returns_ok() {
+        let (reader, writer) = channel();
+        assert!(writer.send(vec![1, 2, 3]).is_ok());
+        assert_eq!(reader.read().unwrap(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn try_recv_once() {
+        let (reader, writer) = channel();
+        assert_eq!(reader.try_recv(), None);
+
+        writer.send(String::from("once")).unwrap();
+        assert_eq!(reader.try_recv().as_deref(), Some("once"));
+        assert_eq!(reader.try_recv(), None);
+        assert!(reader.was_read());
+    }
+
+    #[test]
+    fn read_after_try_recv() {
+        let (reader, writer) = channel();
+        writer.send(1).unwrap();
+        assert_eq!(reader.try_recv(), Some(1));
+
+        assert_eq!(reader.read(), Err(RecvError));
+        assert_eq!(
+            reader.recv_deadline(Instant::now() + Duration::from_secs(1)),
+            Err(RecvTimeoutError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn concurrent_reads_one_wins() {
+        for i in 0..100 {
+            let (reader, writer) = channel();
+            writer.send(i).unwrap();
+
+            // Reader is Sync, both threads race for the same message through &Reader
+            let results: Vec<_> = thread::scope(|s| {
+                let reads: Vec<_> = (0..2).map(|_| s.spawn(|| reader.read())).collect();
+                reads.into_iter().map(|r| r.join().unwrap()).collect()
+            });
+            assert!(results.contains(&Ok(i)));
+            assert!(results.contains(&Err(RecvError)));
+        }
+    }
+
+    #[test]
+    fn was_read() {
+        let (reader, writer) = channel::<u32>();
+        assert!(!reader.was_read());
+
+        writer.send(1).unwrap();
+        assert!(!reader.was_read());
+
+        reader.read().unwrap();
+        assert!(reader.was_read());
+    }
+
+    #[test]
+    fn pool_reuses_channels() {
+        let pool = OneshotPool::new();
+
+        for i in 0..1000 {
+            let (reader, writer) = pool.acquire();
+            thread::spawn(move || writer.send(i).unwrap())
+                .join()
+                .unwrap();
+            assert_eq!(reader.read(), Ok(i));
+        }
+        assert_eq!(pool.allocations(), 1);
+
+        // Writer dropped without sending, the channel is still clean enough to reuse
+        drop(pool.acquire());
+        let (reader, writer) = pool.acquire();
+        writer.send(1).unwrap();
+        assert_eq!(pool.allocations(), 1);
+
+        // Never read, so not reused: the next cycle needs a new channel
+        drop(reader);
+        drop(pool.acquire());
+        assert_eq!(pool.allocations(), 2);
+    }
+
+    #[test]
+    fn state_from_u32() {
+        for state in [
+            State::Empty,
+            State::Ready,
+            State::Reading,
+            State::Read,
+            State::Closed,
+        ] {
+            assert_eq!(State::try_from(state as u32), Ok(state));
+        }
+        assert_eq!(State::try_from(5), Err(5));
+    }
+
+    #[test]
+    fn valid_transitions() {
+        let channel = Channel::<u32>::new();
+
+        assert_eq!(channel.try_mark_ready(), Ok(()));
+        assert_eq!(channel.try_begin_read(), Ok(()));
+        assert_eq!(channel.mark_read(), Ok(()));
+    }
+
+    #[test]
+    fn invalid_transitions() {
+        let channel = Channel::<u32>::new();
+        // Empty: nothing to read yet
+        assert_eq!(channel.try_begin_read(), Err(State::Empty));
+        assert_eq!(channel.mark_read(), Err(State::Empty));
+
+        // Ready: can't send twice or finish a read that never began
+        channel.try_mark_ready().unwrap();
+        assert_eq!(channel.try_mark_ready(), Err(State::Ready));
+        assert_eq!(channel.mark_read(), Err(State::Ready));
+
+        // Reading: a second reader can't begin
+        channel.try_begin_read().unwrap();
+        assert_eq!(channel.try_begin_read(), Err(State::Reading));
+        assert_eq!(channel.try_mark_ready(), Err(State::Reading));
+
+        // Read: terminal
+        channel.mark_read().unwrap();
+        assert_eq!(channel.try_mark_ready(), Err(State::Read));
+        assert_eq!(channel.try_begin_read(), Err(State::Read));
+        assert_eq!(channel.mark_read(), Err(State::Read));
+    }
 }
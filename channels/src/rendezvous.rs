@@ -0,0 +1,215 @@
+use std::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use atomic_wait::{wait, wake_all};
+
+use crate::unbounded::{RecvError, SendError};
+
+/// No value on offer
+const EMPTY: u32 = 0;
+/// Sender wrote a value and waits for the receiver to take it
+const OFFERED: u32 = 1;
+/// Receiver took the value, sender has yet to see it
+const TAKEN: u32 = 2;
+/// Low bits holding one of the above
+const STAGE: u32 = 0b11;
+/// Set on top of the stage once either half is dropped
+const DISCONNECTED: u32 = 0b100;
+
+/// Zero capacity channel: every send waits until the receiver has taken the value
+struct Channel<T> {
+    state: AtomicU32,
+    slot: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send> Send for Channel<T> {}
+
+impl<T> Drop for Channel<T> {
+    fn drop(&mut self) {
+        // A value still on offer was never taken, nobody else will drop it
+        if *self.state.get_mut() & STAGE == OFFERED {
+            unsafe { self.slot.get_mut().assume_init_drop() }
+        }
+    }
+}
+
+pub fn rendezvous<T>() -> (Sender<T>, Receiver<T>) {
+    let channel = Arc::new(Channel {
+        state: AtomicU32::new(EMPTY),
+        slot: UnsafeCell::new(MaybeUninit::uninit()),
+    });
+
+    (
+        Sender {
+            channel: Arc::clone(&channel),
+        },
+        Receiver { channel },
+    )
+}
+
+pub struct Sender<T> {
+    channel: Arc<Channel<T>>,
+}
+
+unsafe impl<T: Send> Send for Sender<T> {}
+
+impl<T> Sender<T> {
+    /// Offers `value` and blocks until the receiver has taken it
+    /// Err with the value back if the receiver is dropped before taking it
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        if self.channel.state.load(Ordering::Relaxed) & DISCONNECTED != 0 {
+            return Err(SendError(value));
+        }
+
+        // The previous send only returned after the receiver confirmed, so the slot is free
+        unsafe { (*self.channel.slot.get()).write(value) };
+        // fetch_or keeps a disconnect that happened in the meantime, the loop below sees it
+        self.channel.state.fetch_or(OFFERED, Ordering::Release);
+        wake_all(&self.channel.state);
+
+        loop {
+            let s = self.channel.state.load(Ordering::Acquire);
+            if s & STAGE == TAKEN {
+                break;
+            }
+            if s & DISCONNECTED != 0 {
+                // Still OFFERED, so the value was never taken and is ours again
+                self.channel.state.fetch_and(!STAGE, Ordering::Relaxed);
+                let value = unsafe { (*self.channel.slot.get()).assume_init_read() };
+                return Err(SendError(value));
+            }
+            wait(&self.channel.state, s)
+        }
+        // Back to EMPTY, keeping a disconnect flag
+        self.channel.state.fetch_and(!STAGE, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.channel.state.fetch_or(DISCONNECTED, Ordering::Relaxed);
+        wake_all(&self.channel.state);
+    }
+}
+
+pub struct Receiver<T> {
+    channel: Arc<Channel<T>>,
+}
+
+unsafe impl<T: Send> Send for Receiver<T> {}
+
+impl<T> Receiver<T> {
+    /// Blocks until a sender offers a value, then takes it and releases the sender
+    /// Err once the sender is dropped, a value is only ever on offer while a send is waiting
+    pub fn recv(&self) -> Result<T, RecvError> {
+        loop {
+            let s = self.channel.state.load(Ordering::Acquire);
+            if s & STAGE == OFFERED {
+                break;
+            }
+            if s & DISCONNECTED != 0 {
+                return Err(RecvError);
+            }
+            wait(&self.channel.state, s)
+        }
+
+        let value = unsafe { (*self.channel.slot.get()).assume_init_read() };
+        // OFFERED -> TAKEN, an add so it can't clear a disconnect flag
+        self.channel
+            .state
+            .fetch_add(TAKEN - OFFERED, Ordering::Release);
+        wake_all(&self.channel.state);
+        Ok(value)
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.channel.state.fetch_or(DISCONNECTED, Ordering::Relaxed);
+        wake_all(&self.channel.state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::rendezvous;
+    use crate::unbounded::{RecvError, SendError};
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn send_waits_for_receiver() {
+        let (sender, receiver) = rendezvous::<String>();
+        let start = Instant::now();
+
+        let sender_thread = thread::spawn(move || {
+            sender.send("hand off".to_string()).unwrap();
+            start.elapsed()
+        });
+
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(receiver.recv().unwrap(), "hand off");
+
+        // The send could only complete once recv took the value
+        assert!(sender_thread.join().unwrap() >= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn sequence() {
+        let (sender, receiver) = rendezvous::<u32>();
+
+        let sender_thread = thread::spawn(move || {
+            for i in 0..100 {
+                sender.send(i).unwrap();
+            }
+        });
+
+        for i in 0..100 {
+            assert_eq!(receiver.recv().unwrap(), i);
+        }
+        sender_thread.join().unwrap();
+        assert_eq!(receiver.recv(), Err(RecvError));
+    }
+
+    #[test]
+    fn receiver_dropped_hands_value_back() {
+        let (sender, receiver) = rendezvous::<String>();
+
+        let sender_thread = thread::spawn(move || sender.send("unwanted".to_string()));
+        thread::sleep(Duration::from_millis(50));
+        drop(receiver);
+
+        // Blocked on the offer, and gets the value back instead of waiting forever
+        assert_eq!(
+            sender_thread.join().unwrap(),
+            Err(SendError("unwanted".to_string()))
+        );
+    }
+
+    #[test]
+    fn sender_dropped_unblocks_receiver() {
+        let (sender, receiver) = rendezvous::<u32>();
+
+        let receiver_thread = thread::spawn(move || receiver.recv());
+        thread::sleep(Duration::from_millis(50));
+        drop(sender);
+
+        assert_eq!(receiver_thread.join().unwrap(), Err(RecvError));
+    }
+
+    #[test]
+    fn send_after_receiver_dropped() {
+        let (sender, receiver) = rendezvous::<u32>();
+        drop(receiver);
+        assert_eq!(sender.send(1), Err(SendError(1)));
+    }
+}
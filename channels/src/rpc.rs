@@ -0,0 +1,55 @@
+use crate::{
+    oneshot::{self, RecvError, Writer},
+    unbounded::Sender,
+};
+
+/// Sends `req` to a server together with a oneshot Writer for the reply, then blocks until the reply arrives
+/// Errors if the server is gone, or drops the Writer without replying
+pub fn call<Req, Resp: Send>(
+    server: &Sender<(Req, Writer<Resp>)>,
+    req: Req,
+) -> Result<Resp, RecvError> {
+    let (reply, writer) = oneshot::channel();
+    // A server that's gone drops the Writer along with the request, which the recv below reports
+    let _ = server.send((req, writer));
+    reply.recv()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::unbounded::unbounded;
+
+    #[test]
+    fn echo_server() {
+        let (server, requests) = unbounded::<(String, Writer<String>)>();
+
+        let server_thread = thread::spawn(move || {
+            while let Some((req, reply)) = requests.recv() {
+                reply.send(format!("echo: {req}")).unwrap();
+            }
+        });
+
+        for i in 0..10 {
+            assert_eq!(call(&server, i.to_string()), Ok(format!("echo: {i}")));
+        }
+
+        drop(server);
+        server_thread.join().unwrap();
+    }
+
+    #[test]
+    fn server_gone() {
+        let (server, requests) = unbounded::<(u32, Writer<u32>)>();
+
+        // Takes one request and drops its Writer without replying
+        let server_thread = thread::spawn(move || drop(requests.recv()));
+        assert_eq!(call(&server, 1), Err(RecvError));
+        server_thread.join().unwrap();
+
+        // Receiver is gone, the request never reaches a server
+        assert_eq!(call(&server, 2), Err(RecvError));
+    }
+}
@@ -0,0 +1,123 @@
+use std::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use atomic_wait::{wait, wake_one};
+
+use crate::oneshot::RecvError;
+
+const EMPTY: u32 = 0;
+const READY: u32 = 1;
+const CLOSED: u32 = 2;
+
+/// Oneshot channel that lives wherever the caller puts it, e.g. on the stack, instead of in an Arc
+/// The halves borrow it, so they can't outlive it, and with thread::scope they can still go to other threads
+/// No allocation and no reference counting, at the price of the halves being tied to a scope
+pub struct Channel<T> {
+    state: AtomicU32,
+    message: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// Same reasoning as the Arc based oneshot: the message is only ever moved between threads, never shared
+unsafe impl<T: Send> Sync for Channel<T> {}
+
+impl<T> Channel<T> {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU32::new(EMPTY),
+            message: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// Hands out the two halves, starting from a fresh channel every time
+    /// Borrowing mutably means the previous halves are gone, so the channel can be reused sequentially
+    pub fn split(&mut self) -> (Sender<'_, T>, Receiver<'_, T>) {
+        // Drops a message the previous receiver never took
+        *self = Self::new();
+        (Sender { channel: self }, Receiver { channel: self })
+    }
+}
+
+impl<T> Default for Channel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for Channel<T> {
+    fn drop(&mut self) {
+        if *self.state.get_mut() == READY {
+            unsafe { self.message.get_mut().assume_init_drop() }
+        }
+    }
+}
+
+pub struct Sender<'a, T> {
+    channel: &'a Channel<T>,
+}
+
+impl<T> Sender<'_, T> {
+    pub fn send(self, message: T) {
+        // The receiver only reads the cell after seeing READY, which only this send stores
+        unsafe { (*self.channel.message.get()).write(message) };
+        self.channel.state.store(READY, Ordering::Release);
+        wake_one(&self.channel.state);
+        // Our Drop would mark the channel closed
+        std::mem::forget(self);
+    }
+}
+
+impl<T> Drop for Sender<'_, T> {
+    /// Dropped without sending, tell the receiver nothing is coming
+    fn drop(&mut self) {
+        self.channel.state.store(CLOSED, Ordering::Relaxed);
+        wake_one(&self.channel.state);
+    }
+}
+
+pub struct Receiver<'a, T> {
+    channel: &'a Channel<T>,
+}
+
+impl<T> Receiver<'_, T> {
+    /// Blocks until the message arrives, or the sender is dropped without sending
+    pub fn recv(self) -> Result<T, RecvError> {
+        loop {
+            match self.channel.state.load(Ordering::Acquire) {
+                READY => {
+                    // Back to EMPTY so the channel doesn't drop the message a second time
+                    self.channel.state.store(EMPTY, Ordering::Relaxed);
+                    return Ok(unsafe { (*self.channel.message.get()).assume_init_read() });
+                }
+                CLOSED => return Err(RecvError),
+                s => wait(&self.channel.state, s),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    // Allocation counting lives in tests/scoped_oneshot_alloc.rs, it needs its own global allocator
+
+    #[test]
+    fn sender_dropped() {
+        let mut channel = Channel::<String>::new();
+        thread::scope(|s| {
+            let (sender, receiver) = channel.split();
+            s.spawn(move || drop(sender));
+            assert_eq!(receiver.recv(), Err(RecvError));
+        });
+
+        // Split resets the closed state
+        let (sender, receiver) = channel.split();
+        sender.send(String::from("again"));
+        assert_eq!(receiver.recv().as_deref(), Ok("again"));
+    }
+}
@@ -0,0 +1,133 @@
+use std::{
+    any::Any,
+    sync::Arc,
+    thread::{self, JoinHandle},
+};
+
+use locks::mutex::Mutex;
+
+use crate::unbounded::{unbounded, Receiver, Sender};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Returned by ThreadPool::execute once every worker has died from a panicking job
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolClosed;
+
+/// Panics of the jobs that killed workers, returned by ThreadPool::join
+#[derive(Debug)]
+pub struct WorkerPanics(pub Vec<Box<dyn Any + Send>>);
+
+/// Fixed number of worker threads running jobs off a shared unbounded channel
+pub struct ThreadPool {
+    jobs: Sender<Job>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ThreadPool {
+    /// Panics if `workers` is 0, a pool without workers would never run anything
+    pub fn new(workers: usize) -> Self {
+        assert!(workers > 0, "thread pool needs at least one worker");
+
+        let (jobs, receiver) = unbounded::<Job>();
+        // The channel has a single receiver, so workers take turns waiting on it
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..workers)
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+                thread::spawn(move || Self::work(&receiver))
+            })
+            .collect();
+
+        Self { jobs, workers }
+    }
+
+    /// Runs jobs until the pool's sender is dropped and every queued job has been taken
+    fn work(receiver: &Mutex<Receiver<Job>>) {
+        // The lock is only held while waiting for a job, not while running it
+        while let Some(job) = receiver.with(|r| r.recv()) {
+            job()
+        }
+    }
+
+    /// Queues `job` to run on one of the workers
+    /// Err once every worker has died from a panicking job, the job is dropped without running
+    pub fn execute<F: FnOnce() + Send + 'static>(&self, job: F) -> Result<(), PoolClosed> {
+        self.jobs.send(Box::new(job)).map_err(|_| PoolClosed)
+    }
+
+    /// Closes the pool and waits for the workers to finish every job still queued
+    /// A panicking job takes its worker down with it, those panics are handed back rather than rethrown
+    pub fn join(self) -> Result<(), WorkerPanics> {
+        drop(self.jobs);
+        let panics: Vec<_> = self
+            .workers
+            .into_iter()
+            .filter_map(|worker| worker.join().err())
+            .collect();
+
+        if panics.is_empty() {
+            Ok(())
+        } else {
+            Err(WorkerPanics(panics))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicU64, Ordering},
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+
+    #[test]
+    fn runs_every_job() {
+        let pool = ThreadPool::new(4);
+        let count = Arc::new(AtomicU64::new(0));
+
+        for _ in 0..1000 {
+            let count = Arc::clone(&count);
+            pool.execute(move || {
+                count.fetch_add(1, Ordering::Relaxed);
+            })
+            .unwrap();
+        }
+
+        pool.join().unwrap();
+        assert_eq!(count.load(Ordering::Relaxed), 1000);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one worker")]
+    fn no_workers() {
+        ThreadPool::new(0);
+    }
+
+    #[test]
+    fn panicking_jobs_are_reported() {
+        let pool = ThreadPool::new(2);
+        pool.execute(|| panic!("bad job")).unwrap();
+
+        let panics = pool.join().unwrap_err().0;
+        assert_eq!(panics.len(), 1);
+        assert_eq!(panics[0].downcast_ref::<&str>(), Some(&"bad job"));
+    }
+
+    #[test]
+    fn execute_after_workers_died() {
+        let pool = ThreadPool::new(1);
+        pool.execute(|| panic!("bad job")).unwrap();
+
+        // The channel closes once the only worker has unwound
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while pool.execute(|| {}).is_ok() {
+            assert!(Instant::now() < deadline, "worker never died");
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(pool.join().is_err());
+    }
+}
@@ -0,0 +1,546 @@
+use std::{
+    cell::{Cell, UnsafeCell},
+    marker::PhantomData,
+    mem::MaybeUninit,
+    ptr::{self, NonNull},
+    sync::{
+        atomic::{AtomicBool, AtomicPtr, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use atomic_wait::{wait, wake_one};
+use locks::cancel::{CancellationToken, Cancelled};
+
+use crate::{defer::guard, free_list::FreeList};
+
+/// Returned by send once the receiver is gone, handing the value back
+#[derive(Debug, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+/// Every sender was dropped and nothing is left to receive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError;
+
+/// Why Receiver::recv_cancellable returned without a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvCancelError {
+    /// The token was cancelled while waiting
+    Cancelled,
+    /// Every sender was dropped and nothing is left to receive
+    Disconnected,
+}
+
+struct Node<T> {
+    next: AtomicPtr<Node<T>>,
+    /// Uninit for the stub node at the front of the queue, its value was already received
+    value: MaybeUninit<T>,
+    /// Values queued up to and including this one, i.e. its sequence number + 1, see Sender::send_indexed
+    /// UNCOUNTED from the node being swapped in until its sender has read the previous node's count
+    sent: AtomicU64,
+}
+
+/// Node::sent before it's set
+const UNCOUNTED: u64 = u64::MAX;
+
+/// Intrusive MPSC queue (Vyukov): senders push at the back with a single swap,
+/// the receiver walks from the front. The front node is always a stub whose value was already taken
+///
+/// Usually built through unbounded(), or constructed up front (e.g. as part of a larger struct) and split later
+pub struct Channel<T> {
+    /// Last node pushed, every sender swaps itself in here
+    back: AtomicPtr<Node<T>>,
+    /// Stub node, only touched by the receiver
+    front: UnsafeCell<*mut Node<T>>,
+    /// Received nodes go back here to be reused by the next sends
+    nodes: FreeList<Node<T>>,
+    /// Bumped on every send and on disconnect, the receiver sleeps on it while the queue is empty
+    signal: AtomicU32,
+    senders: AtomicUsize,
+    receiver_alive: AtomicBool,
+    /// Set once the channel was split, there can only ever be one receiver
+    split: AtomicBool,
+}
+
+unsafe impl<T: Send> Send for Channel<T> {}
+unsafe impl<T: Send> Sync for Channel<T> {}
+
+impl<T> Channel<T> {
+    pub fn new() -> Self {
+        let nodes = FreeList::new();
+        let stub = nodes
+            .alloc(Node {
+                next: AtomicPtr::new(ptr::null_mut()),
+                value: MaybeUninit::uninit(),
+                // Stands in for the value before the first one sent
+                sent: AtomicU64::new(0),
+            })
+            .as_ptr();
+
+        Self {
+            back: AtomicPtr::new(stub),
+            front: UnsafeCell::new(stub),
+            nodes,
+            signal: AtomicU32::new(0),
+            senders: AtomicUsize::new(1),
+            receiver_alive: AtomicBool::new(true),
+            split: AtomicBool::new(false),
+        }
+    }
+
+    /// Hands out the Sender and the one Receiver, panics if the channel was already split
+    pub fn into_halves(self: Arc<Self>) -> (Sender<T>, Receiver<T>) {
+        if self.split.swap(true, Ordering::Relaxed) {
+            panic!("channel was already split");
+        }
+
+        (
+            Sender {
+                channel: Arc::clone(&self),
+            },
+            Receiver {
+                channel: self,
+                _not_sync: PhantomData,
+            },
+        )
+    }
+
+    /// Queues `value`, returning its sequence number: how many values were queued before it
+    fn push(&self, value: T) -> u64 {
+        let node = self
+            .nodes
+            .alloc(Node {
+                next: AtomicPtr::new(ptr::null_mut()),
+                value: MaybeUninit::new(value),
+                sent: AtomicU64::new(UNCOUNTED),
+            })
+            .as_ptr();
+
+        // AcqRel: Release publishes the node's value to the next sender, Acquire makes prev safe to link to
+        let prev = self.back.swap(node, Ordering::AcqRel);
+
+        // The swaps order the queue, so the count carries on from prev's
+        // prev's sender sets it right after its own swap, only that short gap is waited out
+        let before = loop {
+            // Acquire pairs with the Release below in prev's sender
+            match unsafe { (*prev).sent.load(Ordering::Acquire) } {
+                UNCOUNTED => std::hint::spin_loop(),
+                sent => break sent,
+            }
+        };
+        unsafe { (*node).sent.store(before + 1, Ordering::Release) };
+
+        // Until this store the receiver sees the queue end at prev, so a send isn't visible before it's linked
+        unsafe { (*prev).next.store(node, Ordering::Release) };
+        before
+    }
+
+    /// Takes the value after the stub, which then becomes the new stub
+    ///
+    /// # Safety
+    /// Only the receiver may pop
+    unsafe fn pop(&self) -> Option<(u64, T)> {
+        let front = unsafe { *self.front.get() };
+        // Acquire pairs with the Release in push, making the value visible
+        let next = unsafe { (*front).next.load(Ordering::Acquire) };
+        if next.is_null() {
+            return None;
+        }
+
+        // Set before the node was linked, the Acquire above makes it visible
+        let value = unsafe {
+            let index = (*next).sent.load(Ordering::Relaxed) - 1;
+            (index, (*next).value.assume_init_read())
+        };
+        unsafe {
+            *self.front.get() = next;
+            // Its next pointer is set, so no sender touches the old stub anymore
+            self.nodes.free(NonNull::new_unchecked(front));
+        }
+        Some(value)
+    }
+}
+
+impl<T> Default for Channel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for Channel<T> {
+    fn drop(&mut self) {
+        let (nodes, front) = (&self.nodes, self.front.get());
+        // The last stub is freed even if dropping one of the values below panics
+        let _free_stub = guard(|| unsafe { nodes.free(NonNull::new_unchecked(*front)) });
+
+        // Drop whatever was never received, the rest still is if dropping one value panics
+        while let Some(value) = unsafe { self.pop() } {
+            let rest = guard(|| while unsafe { self.pop() }.is_some() {});
+            drop(value);
+            rest.cancel();
+        }
+    }
+}
+
+/// Multi producer, single consumer channel without a capacity limit: send never blocks
+pub fn unbounded<T>() -> (Sender<T>, Receiver<T>) {
+    Arc::new(Channel::new()).into_halves()
+}
+
+pub struct Sender<T> {
+    channel: Arc<Channel<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Queues `value`, failing only if the receiver was dropped
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        self.send_indexed(value).map(|_| ())
+    }
+
+    /// Like send, but returns the sequence number the value was queued at, unique across all senders
+    /// The number is the value's position in the queue, so the receiver gets values in the order of their numbers
+    pub fn send_indexed(&self, value: T) -> Result<u64, SendError<T>> {
+        if !self.channel.receiver_alive.load(Ordering::Relaxed) {
+            return Err(SendError(value));
+        }
+
+        let index = self.channel.push(value);
+        self.channel.signal.fetch_add(1, Ordering::Release);
+        wake_one(&self.channel.signal);
+        Ok(index)
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.channel.senders.fetch_add(1, Ordering::Relaxed);
+        Self {
+            channel: Arc::clone(&self.channel),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        // Last sender gone: wake the receiver so it can notice the disconnect
+        if self.channel.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.channel.signal.fetch_add(1, Ordering::Release);
+            wake_one(&self.channel.signal);
+        }
+    }
+}
+
+pub struct Receiver<T> {
+    channel: Arc<Channel<T>>,
+    /// Single consumer: the receiver can move between threads, but not be shared by them
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl<T> Receiver<T> {
+    /// Blocks until a value is sent, None once every sender is dropped and the queue is drained
+    pub fn recv(&self) -> Option<T> {
+        self.recv_indexed().ok().map(|(_, value)| value)
+    }
+
+    /// Like recv, but also returns the sequence number the value was sent with
+    /// Numbers arrive in order without gaps, they count every sender's values
+    pub fn recv_indexed(&self) -> Result<(u64, T), RecvError> {
+        self.recv_until(None).map_err(|_| RecvError)
+    }
+
+    /// recv that gives up once `token` is cancelled, even with senders still alive
+    pub fn recv_cancellable(&self, token: &CancellationToken) -> Result<T, RecvCancelError> {
+        self.recv_until(Some(token)).map(|(_, value)| value)
+    }
+
+    fn recv_until(&self, token: Option<&CancellationToken>) -> Result<(u64, T), RecvCancelError> {
+        loop {
+            // Loaded before checking the queue, so a send landing in between makes the wait return
+            let signal = self.channel.signal.load(Ordering::Acquire);
+
+            if let Some(entry) = unsafe { self.channel.pop() } {
+                return Ok(entry);
+            }
+            if self.channel.senders.load(Ordering::Acquire) == 0 {
+                // A last send may have been linked after the pop above
+                return unsafe { self.channel.pop() }.ok_or(RecvCancelError::Disconnected);
+            }
+
+            match token {
+                Some(token) => token
+                    .wait(&self.channel.signal, signal)
+                    .map_err(|Cancelled| RecvCancelError::Cancelled)?,
+                None => wait(&self.channel.signal, signal),
+            }
+        }
+    }
+
+    /// Takes every value currently queued without blocking, oldest first
+    /// Sends racing with the drain may or may not make it in, later ones stay queued for the next recv
+    pub fn drain(&self) -> Vec<T> {
+        let mut values = Vec::new();
+        while let Some((_, value)) = unsafe { self.channel.pop() } {
+            values.push(value);
+        }
+        values
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.channel.receiver_alive.store(false, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+
+    #[test]
+    fn bursty_producers() {
+        let (sender, receiver) = unbounded();
+
+        let producers: Vec<_> = (0..4)
+            .map(|p| {
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    for burst in 0..10 {
+                        for i in 0..100 {
+                            sender.send((p, burst * 100 + i)).unwrap();
+                        }
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                })
+            })
+            .collect();
+        drop(sender);
+
+        // Each producer's values arrive in the order they were sent
+        let mut next = [0; 4];
+        while let Some((p, i)) = receiver.recv() {
+            assert_eq!(next[p], i);
+            next[p] += 1;
+        }
+        assert_eq!(next, [1000; 4]);
+
+        for p in producers {
+            p.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn send_indexed_unique() {
+        let (sender, receiver) = unbounded();
+
+        let indices: Vec<u64> = thread::scope(|s| {
+            let producers: Vec<_> = (0..2)
+                .map(|_| {
+                    let sender = sender.clone();
+                    s.spawn(move || {
+                        (0..500)
+                            .map(|i| sender.send_indexed(i).unwrap())
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            producers
+                .into_iter()
+                .flat_map(|p| p.join().unwrap())
+                .collect()
+        });
+        drop(sender);
+
+        let mut sorted = indices.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..1000).collect::<Vec<_>>());
+        assert_eq!(receiver.drain().len(), 1000);
+    }
+
+    #[test]
+    fn send_indexed_queue_order() {
+        let (sender, receiver) = unbounded();
+
+        let mut sent: Vec<(u64, (usize, u64))> = thread::scope(|s| {
+            let producers: Vec<_> = (0..4)
+                .map(|p| {
+                    let sender = sender.clone();
+                    s.spawn(move || {
+                        (0..250)
+                            .map(|i| (sender.send_indexed((p, i)).unwrap(), (p, i)))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            producers
+                .into_iter()
+                .flat_map(|p| p.join().unwrap())
+                .collect()
+        });
+        drop(sender);
+
+        // Each value arrives at the number its sender got back
+        sent.sort();
+        let received: Vec<_> = std::iter::from_fn(|| receiver.recv_indexed().ok()).collect();
+        assert_eq!(received, sent);
+    }
+
+    #[test]
+    fn recv_indexed_contiguous() {
+        let (sender, receiver) = unbounded();
+
+        let producer = thread::spawn(move || {
+            for i in 0..1000 {
+                sender.send(i * 10).unwrap();
+            }
+        });
+
+        let mut expected = 0;
+        while let Ok((index, value)) = receiver.recv_indexed() {
+            assert_eq!(index, expected);
+            assert_eq!(value, index * 10);
+            expected += 1;
+        }
+        assert_eq!(expected, 1000);
+        producer.join().unwrap();
+    }
+
+    #[test]
+    fn split_stored_channel() {
+        struct Service {
+            requests: Arc<Channel<String>>,
+        }
+
+        let service = Service {
+            requests: Arc::new(Channel::new()),
+        };
+
+        let (sender, receiver) = Arc::clone(&service.requests).into_halves();
+        sender.send("hello".to_string()).unwrap();
+        assert_eq!(receiver.recv().as_deref(), Some("hello"));
+    }
+
+    #[test]
+    #[should_panic(expected = "channel was already split")]
+    fn split_twice() {
+        let channel = Arc::new(Channel::<u32>::new());
+        let _halves = Arc::clone(&channel).into_halves();
+        let _again = channel.into_halves();
+    }
+
+    #[test]
+    fn disconnect() {
+        let (sender, receiver) = unbounded();
+        let second = sender.clone();
+
+        sender.send(1).unwrap();
+        drop(sender);
+        second.send(2).unwrap();
+        drop(second);
+
+        // Queued values are still delivered after the last sender is gone
+        assert_eq!(receiver.recv(), Some(1));
+        assert_eq!(receiver.recv(), Some(2));
+        assert_eq!(receiver.recv(), None);
+
+        let (sender, receiver) = unbounded();
+        drop(receiver);
+        assert_eq!(sender.send(3), Err(SendError(3)));
+    }
+
+    #[test]
+    fn drops_unreceived() {
+        struct DropCounter<'a>(&'a AtomicUsize);
+
+        impl Drop for DropCounter<'_> {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let drops = AtomicUsize::new(0);
+        let (sender, receiver) = unbounded();
+        for _ in 0..3 {
+            assert!(sender.send(DropCounter(&drops)).is_ok());
+        }
+        drop(receiver.recv());
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+
+        drop((sender, receiver));
+        assert_eq!(drops.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn drain_takes_everything() {
+        let (sender, receiver) = unbounded();
+        assert!(receiver.drain().is_empty());
+
+        for i in 0..100 {
+            sender.send(i).unwrap();
+        }
+        assert_eq!(receiver.drain(), (0..100).collect::<Vec<_>>());
+        assert!(receiver.drain().is_empty());
+
+        sender.send(100).unwrap();
+        assert_eq!(receiver.recv(), Some(100));
+    }
+
+    #[test]
+    fn recv_blocks_until_send() {
+        let (sender, receiver) = unbounded();
+
+        let t = thread::spawn(move || receiver.recv());
+        thread::sleep(Duration::from_millis(100));
+        sender.send("late").unwrap();
+
+        assert_eq!(t.join().unwrap(), Some("late"));
+    }
+
+    #[test]
+    fn cancel_blocked_recv() {
+        let (sender, receiver) = unbounded::<u32>();
+        let token = Arc::new(CancellationToken::new());
+
+        let t = thread::spawn({
+            let token = Arc::clone(&token);
+            move || {
+                let result = receiver.recv_cancellable(&token);
+                (result, receiver)
+            }
+        });
+        thread::sleep(Duration::from_millis(100));
+
+        let cancelled_at = Instant::now();
+        token.cancel();
+        let (result, receiver) = t.join().unwrap();
+        assert_eq!(result, Err(RecvCancelError::Cancelled));
+        assert!(cancelled_at.elapsed() < Duration::from_secs(1));
+
+        // Values still get through to an uncancelled recv, and disconnect is reported as before
+        sender.send(1).unwrap();
+        drop(sender);
+        let token = CancellationToken::new();
+        assert_eq!(receiver.recv_cancellable(&token), Ok(1));
+        assert_eq!(
+            receiver.recv_cancellable(&token),
+            Err(RecvCancelError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn steady_state_reuses_nodes() {
+        let (sender, receiver) = unbounded();
+
+        for i in 0..10000 {
+            sender.send(i).unwrap();
+            assert_eq!(receiver.recv(), Some(i));
+        }
+
+        // The stub and the node in flight, instead of one allocation per send
+        assert_eq!(receiver.channel.nodes.allocations(), 2);
+    }
+}
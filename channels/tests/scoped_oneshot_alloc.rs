@@ -0,0 +1,52 @@
+//! Own test binary: the counting allocator replaces the global allocator for everything linked into it
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    thread,
+};
+
+use channels::scoped_oneshot::Channel;
+
+/// Counts allocations per thread, so the test can check the channel path makes none
+struct CountingAlloc;
+
+thread_local!(static ALLOCATIONS: Cell<usize> = const { Cell::new(0) });
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(Cell::get)
+}
+
+#[test]
+fn send_recv_without_allocating() {
+    let mut channel = Channel::new();
+
+    for i in 0..3u64 {
+        thread::scope(|s| {
+            let (sender, receiver) = channel.split();
+            s.spawn(move || {
+                let before = allocations();
+                sender.send(i);
+                assert_eq!(allocations(), before);
+            });
+
+            let before = allocations();
+            assert_eq!(receiver.recv(), Ok(i));
+            assert_eq!(allocations(), before);
+        });
+    }
+}
@@ -0,0 +1,104 @@
+use crate::spin_lock::SpinLock;
+
+/// Cell for Copy values of any size that can be shared between threads
+/// Every operation takes the spin lock just long enough to copy the value in or out
+pub struct AtomicCell<T: Copy> {
+    lock: SpinLock<T>,
+}
+
+impl<T: Copy> AtomicCell<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            lock: SpinLock::new(value),
+        }
+    }
+
+    pub fn load(&self) -> T {
+        *self.lock.lock()
+    }
+
+    pub fn store(&self, value: T) {
+        *self.lock.lock() = value;
+    }
+
+    /// Stores `value`, returning the previous one
+    pub fn swap(&self, value: T) -> T {
+        std::mem::replace(&mut *self.lock.lock(), value)
+    }
+
+    /// Stores `new` if the value equals `current`, returning the previous value either way
+    /// The store happened if the returned value equals `current`
+    pub fn compare_and_swap(&self, current: T, new: T) -> T
+    where
+        T: PartialEq,
+    {
+        let mut guard = self.lock.lock();
+        let previous = *guard;
+        if previous == current {
+            *guard = new;
+        }
+        previous
+    }
+
+    pub fn into_inner(self) -> T {
+        self.load()
+    }
+}
+
+impl<T: Copy + Default> Default for AtomicCell<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, thread};
+
+    use super::*;
+
+    #[test]
+    fn swap_never_tears() {
+        const THREADS: u64 = 8;
+        const SWAPS: u64 = 1000;
+
+        // Both halves always hold the same number, a torn copy would mix two of them
+        let cell = AtomicCell::new((0u64, 0u64));
+
+        let observed: HashSet<_> = thread::scope(|s| {
+            let handles: Vec<_> = (0..THREADS)
+                .map(|t| {
+                    let cell = &cell;
+                    s.spawn(move || {
+                        (0..SWAPS)
+                            .map(|i| {
+                                let v = t * SWAPS + i + 1;
+                                cell.swap((v, v))
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().unwrap())
+                .collect()
+        });
+
+        let stored: HashSet<_> = (0..=THREADS * SWAPS).map(|v| (v, v)).collect();
+        assert!(observed.is_subset(&stored));
+        assert!(stored.contains(&cell.load()));
+        // Each stored value was swapped out exactly once, except the one still in the cell
+        assert_eq!(observed.len() as u64, THREADS * SWAPS);
+    }
+
+    #[test]
+    fn compare_and_swap() {
+        let cell = AtomicCell::new(1);
+        assert_eq!(cell.compare_and_swap(2, 3), 1);
+        assert_eq!(cell.load(), 1);
+        assert_eq!(cell.compare_and_swap(1, 3), 1);
+        cell.store(cell.load() + 1);
+        assert_eq!(cell.into_inner(), 4);
+    }
+}
@@ -0,0 +1,96 @@
+use std::hint::spin_loop;
+
+/// Default spin phase: attempts before sleeping, each pausing for twice the spin hints of the last,
+/// starting from a single hint and capped at 2^SPIN_MAX_SHIFT
+pub(crate) const SPIN_ATTEMPTS: u32 = 10;
+pub(crate) const SPIN_MAX_SHIFT: u32 = 6;
+
+/// Decides whether a lock keeps spinning on a contended acquire
+/// - Mutex: once this returns false the thread sleeps on the futex
+/// - SpinLock: once this returns false the thread yields to the scheduler between attempts
+pub trait BackoffPolicy {
+    /// `attempt` starts at 0 and increments after every failed acquire
+    fn should_spin(&self, attempt: u32) -> bool;
+}
+
+/// Spin forever, the default for SpinLock
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Spin;
+
+impl BackoffPolicy for Spin {
+    fn should_spin(&self, _attempt: u32) -> bool {
+        spin_loop();
+        true
+    }
+}
+
+/// Spin a fixed number of attempts back to back
+#[derive(Debug, Clone, Copy)]
+pub struct FixedSpin(pub u32);
+
+impl BackoffPolicy for FixedSpin {
+    fn should_spin(&self, attempt: u32) -> bool {
+        attempt < self.0
+    }
+}
+
+/// Spin `limit` attempts, pausing for 2^attempt spin hints (capped at 2^max_shift) before each one
+/// Backing off gives the holder a chance to release without every waiter hammering the cache line
+/// The default for Mutex, with its own limits
+#[derive(Debug, Clone, Copy)]
+pub struct ExponentialBackoff {
+    pub limit: u32,
+    pub max_shift: u32,
+}
+
+impl Default for ExponentialBackoff {
+    fn default() -> Self {
+        Self {
+            limit: SPIN_ATTEMPTS,
+            max_shift: SPIN_MAX_SHIFT,
+        }
+    }
+}
+
+impl BackoffPolicy for ExponentialBackoff {
+    fn should_spin(&self, attempt: u32) -> bool {
+        if attempt >= self.limit {
+            return false;
+        }
+
+        for _ in 0..1u32 << attempt.min(self.max_shift) {
+            spin_loop();
+        }
+        true
+    }
+}
+
+/// Never spin, block (or yield) straight away
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoSpin;
+
+impl BackoffPolicy for NoSpin {
+    fn should_spin(&self, _attempt: u32) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spin_limits() {
+        assert!(Spin.should_spin(u32::MAX));
+        assert!(FixedSpin(3).should_spin(2));
+        assert!(!FixedSpin(3).should_spin(3));
+        assert!(!NoSpin.should_spin(0));
+
+        let exp = ExponentialBackoff {
+            limit: 4,
+            max_shift: 2,
+        };
+        assert!((0..4).all(|a| exp.should_spin(a)));
+        assert!(!exp.should_spin(4));
+    }
+}
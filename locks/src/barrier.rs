@@ -0,0 +1,96 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use atomic_wait::{wait, wake_all};
+
+/// Blocks threads until `n` of them have called wait, then releases them all together
+/// Resets itself afterwards, so the same barrier can separate any number of phases
+pub struct Barrier {
+    n: u32,
+    /// Threads that have arrived in the current generation
+    arrived: AtomicU32,
+    /// Bumped by the last thread to arrive, waiters sleep on it
+    /// Waiting on the generation rather than the arrival count means a thread already in the next
+    /// phase can't be released by the previous one's wake up
+    generation: AtomicU32,
+}
+
+/// Returned from Barrier::wait, tells the one thread per generation that's the leader
+#[derive(Debug, Clone, Copy)]
+pub struct BarrierWaitResult(bool);
+
+impl BarrierWaitResult {
+    pub fn is_leader(&self) -> bool {
+        self.0
+    }
+}
+
+impl Barrier {
+    /// Panics if n doesn't fit in a u32
+    pub fn new(n: usize) -> Self {
+        Self {
+            n: n.try_into().expect("too many threads for a barrier"),
+            arrived: AtomicU32::new(0),
+            generation: AtomicU32::new(0),
+        }
+    }
+
+    /// Blocks until n threads are waiting, the last one to arrive is the leader
+    pub fn wait(&self) -> BarrierWaitResult {
+        // Read before arriving: the generation can't move on until this thread has arrived
+        let generation = self.generation.load(Ordering::Relaxed);
+
+        // AcqRel so the leader sees everything the others did before arriving
+        if self.arrived.fetch_add(1, Ordering::AcqRel) + 1 >= self.n {
+            // Reset before releasing anyone, threads only get into the next phase after the bump below
+            self.arrived.store(0, Ordering::Relaxed);
+            self.generation.fetch_add(1, Ordering::Release);
+            wake_all(&self.generation);
+            return BarrierWaitResult(true);
+        }
+
+        while self.generation.load(Ordering::Acquire) == generation {
+            wait(&self.generation, generation);
+        }
+        BarrierWaitResult(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::atomic::AtomicUsize, thread};
+
+    use super::*;
+
+    #[test]
+    fn one_leader_per_phase() {
+        const THREADS: usize = 8;
+        const PHASES: usize = 20;
+
+        let barrier = Barrier::new(THREADS);
+        let work = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            for _ in 0..THREADS {
+                s.spawn(|| {
+                    for phase in 0..PHASES {
+                        if barrier.wait().is_leader() {
+                            work.fetch_add(1, Ordering::Relaxed);
+                        }
+                        barrier.wait();
+                        // Everyone sees this phase's leader work, and only this phase's
+                        assert_eq!(work.load(Ordering::Relaxed), phase + 1);
+                    }
+                });
+            }
+        });
+
+        assert_eq!(work.load(Ordering::Relaxed), PHASES);
+    }
+
+    #[test]
+    fn single_thread_is_leader() {
+        let barrier = Barrier::new(1);
+        assert!(barrier.wait().is_leader());
+        assert!(barrier.wait().is_leader());
+    }
+}
@@ -0,0 +1,86 @@
+use std::{
+    ops::DerefMut,
+    sync::Barrier,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::lockable::Lockable;
+
+/// Timings from one contention_bench run
+#[derive(Debug, Clone, Copy)]
+pub struct BenchStats {
+    /// Wall clock time from the threads being released until the last one finished
+    pub total: Duration,
+    /// Fastest thread's time for all of its acquisitions
+    pub min: Duration,
+    /// Slowest thread's time for all of its acquisitions
+    pub max: Duration,
+}
+
+/// Spawns `threads` threads that each lock `lock` `iterations` times to bump the counter behind it
+/// Needs an exclusive lock, for an RwLock that's RwLock::write_lockable
+pub fn contention_bench<'l, L>(lock: &'l L, threads: usize, iterations: usize) -> BenchStats
+where
+    L: Lockable<u64> + Sync,
+    L::Guard<'l>: DerefMut,
+{
+    // Everyone starts together, otherwise the first threads finish before the last are spawned
+    let start = Barrier::new(threads + 1);
+
+    let (begin, per_thread) = thread::scope(|s| {
+        let handles: Vec<_> = (0..threads)
+            .map(|_| {
+                let start = &start;
+                s.spawn(move || {
+                    start.wait();
+                    let begin = Instant::now();
+                    for _ in 0..iterations {
+                        *L::lock(lock) += 1;
+                    }
+                    begin.elapsed()
+                })
+            })
+            .collect();
+
+        // Taken before releasing the threads, so it's earlier than any of theirs
+        let begin = Instant::now();
+        start.wait();
+        let per_thread: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        (begin, per_thread)
+    });
+
+    BenchStats {
+        total: begin.elapsed(),
+        min: per_thread.iter().min().copied().unwrap_or_default(),
+        max: per_thread.iter().max().copied().unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mutex::Mutex, rwlock::RwLock, spin_lock::SpinLock};
+
+    const THREADS: usize = 4;
+    const ITERATIONS: usize = 1000;
+
+    fn run<'l, L>(lock: &'l L)
+    where
+        L: Lockable<u64> + Sync,
+        L::Guard<'l>: DerefMut,
+    {
+        let stats = contention_bench(lock, THREADS, ITERATIONS);
+        assert!(stats.min <= stats.max);
+        assert!(stats.max <= stats.total);
+        assert_eq!(*lock.lock(), (THREADS * ITERATIONS) as u64);
+    }
+
+    #[test]
+    fn counts_every_acquisition() {
+        run(&Mutex::new(0));
+        run(&SpinLock::new(0));
+        let rwlock = RwLock::new(0);
+        run(&rwlock.write_lockable());
+    }
+}
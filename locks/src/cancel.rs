@@ -0,0 +1,131 @@
+use std::{
+    fmt, ptr,
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+};
+
+use atomic_wait::{wait, wake_all};
+
+use crate::mutex::Mutex;
+
+/// Returned by a wait that was interrupted by CancellationToken::cancel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("wait was cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Futex a thread is sleeping on through CancellationToken::wait
+struct Sleeper(*const AtomicU32);
+
+// Only dereferenced by cancel, while the sleeper is still registered, see CancellationToken::wait
+unsafe impl Send for Sleeper {}
+
+/// One way switch for interrupting blocked waits, e.g. to shut down threads parked on a channel
+/// A thread can only sleep on one futex at a time, so instead of having its own the token
+/// remembers the futexes its waiters sleep on and bumps them all on cancel
+pub struct CancellationToken {
+    cancelled: AtomicBool,
+    sleepers: Mutex<Vec<Sleeper>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self {
+            cancelled: AtomicBool::new(false),
+            sleepers: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Makes every current and future wait on this token return Cancelled
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+        self.sleepers.with(|sleepers| {
+            for sleeper in sleepers.iter() {
+                // Still registered, so its waiter hasn't returned and the futex is alive
+                let futex = unsafe { &*sleeper.0 };
+                futex.fetch_add(1, Ordering::Release);
+                wake_all(futex);
+            }
+        });
+    }
+
+    /// atomic_wait::wait(futex, expected), but returning early with Cancelled once the token is cancelled
+    /// Cancel interrupts the wait by incrementing `futex`, so it must be a counter waiters only
+    /// compare against, like a channel's signal, never a lock state
+    pub fn wait(&self, futex: &AtomicU32, expected: u32) -> Result<(), Cancelled> {
+        self.sleepers.with(|sleepers| sleepers.push(Sleeper(futex)));
+        // Checked after registering: either cancel saw us in the list and bumped the futex,
+        // or it set the flag before taking the list lock, and we see it here
+        if !self.is_cancelled() {
+            wait(futex, expected);
+        }
+        self.sleepers.with(|sleepers| {
+            let index = sleepers
+                .iter()
+                .position(|sleeper| ptr::eq(sleeper.0, futex))
+                .expect("registered above");
+            sleepers.swap_remove(index);
+        });
+
+        if self.is_cancelled() {
+            return Err(Cancelled);
+        }
+        Ok(())
+    }
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+
+    #[test]
+    fn cancel_wakes_waiter() {
+        let token = CancellationToken::new();
+        let futex = AtomicU32::new(0);
+
+        thread::scope(|s| {
+            let waiter = s.spawn(|| token.wait(&futex, 0));
+            thread::sleep(Duration::from_millis(50));
+            token.cancel();
+            assert_eq!(waiter.join().unwrap(), Err(Cancelled));
+        });
+    }
+
+    #[test]
+    fn already_cancelled() {
+        let token = CancellationToken::new();
+        token.cancel();
+
+        let start = Instant::now();
+        assert_eq!(token.wait(&AtomicU32::new(0), 0), Err(Cancelled));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn uncancelled_wait_returns_ok() {
+        let token = CancellationToken::new();
+        // Futex doesn't hold the expected value, so the wait returns straight away
+        assert_eq!(token.wait(&AtomicU32::new(1), 0), Ok(()));
+        assert!(token.sleepers.with(|sleepers| sleepers.is_empty()));
+    }
+}
@@ -0,0 +1,149 @@
+use std::{
+    cell::UnsafeCell,
+    fmt,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Returned when the thread asking for the lock already holds it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WouldDeadlock;
+
+impl fmt::Display for WouldDeadlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("lock is already held by this thread")
+    }
+}
+
+impl std::error::Error for WouldDeadlock {}
+
+/// Spin lock that remembers which thread holds it, so relocking from that thread
+/// reports an error instead of spinning forever
+/// Meant for catching self-deadlocks while developing, SpinLock is cheaper otherwise
+pub struct CheckedSpinLock<T> {
+    /// Id of the holding thread, 0 when unlocked
+    owner: AtomicUsize,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for CheckedSpinLock<T> {}
+
+/// Nonzero and unique among running threads: the address of a thread local
+fn current_thread_id() -> usize {
+    thread_local!(static ID: u8 = const { 0 });
+    ID.with(|id| id as *const u8 as usize)
+}
+
+impl<T> CheckedSpinLock<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            owner: AtomicUsize::new(0),
+            data: UnsafeCell::new(value),
+        }
+    }
+
+    /// Spins until the lock is free, unless this thread is the one holding it
+    pub fn lock(&self) -> Result<CheckedGuard<'_, T>, WouldDeadlock> {
+        let me = current_thread_id();
+        loop {
+            match self
+                .owner
+                .compare_exchange_weak(0, me, Ordering::Acquire, Ordering::Relaxed)
+            {
+                Ok(_) => break,
+                // Only this thread ever stores its own id, so seeing it means we hold the lock
+                Err(owner) if owner == me => return Err(WouldDeadlock),
+                Err(_) => std::hint::spin_loop(),
+            }
+        }
+
+        Ok(CheckedGuard {
+            lock: self,
+            _not_send: PhantomData,
+        })
+    }
+}
+
+/// ```compile_fail,E0277
+/// use std::cell::Cell;
+/// use locks::checked_spin_lock::CheckedGuard;
+///
+/// fn assert_sync<T: Sync>() {}
+/// assert_sync::<CheckedGuard<'static, Cell<u32>>>();
+/// ```
+///
+/// The guard stays on the thread that locked, the owner id it unlocks is that thread's:
+///
+/// ```compile_fail,E0277
+/// use std::thread;
+/// use locks::checked_spin_lock::CheckedSpinLock;
+///
+/// let lock: &'static _ = Box::leak(Box::new(CheckedSpinLock::new(0)));
+/// let guard = lock.lock().unwrap();
+/// thread::spawn(move || drop(guard));
+/// ```
+pub struct CheckedGuard<'a, T> {
+    lock: &'a CheckedSpinLock<T>,
+    /// Moved to another thread, the locking thread would get WouldDeadlock for a lock it no longer
+    /// holds, and the new holder would spin on its own lock instead
+    _not_send: PhantomData<*const ()>,
+}
+
+/// Sharing the guard shares &T, the lock's own Sync only asks for T: Send
+unsafe impl<T: Sync> Sync for CheckedGuard<'_, T> {}
+
+impl<T> Deref for CheckedGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for CheckedGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for CheckedGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.owner.store(0, Ordering::Release)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn reentry_is_an_error() {
+        let lock = CheckedSpinLock::new(0);
+
+        let mut guard = lock.lock().unwrap();
+        assert_eq!(lock.lock().err(), Some(WouldDeadlock));
+        *guard += 1;
+        drop(guard);
+
+        assert_eq!(*lock.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn other_threads_wait() {
+        let lock = CheckedSpinLock::new(0);
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        *lock.lock().unwrap() += 1;
+                    }
+                });
+            }
+        });
+
+        assert_eq!(*lock.lock().unwrap(), 4000);
+    }
+}
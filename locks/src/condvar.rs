@@ -0,0 +1,106 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use atomic_wait::{wait, wake_all, wake_one};
+
+use crate::{
+    backoff::BackoffPolicy,
+    cancel::{CancellationToken, Cancelled},
+    mutex::{LockResult, MutexGuard},
+};
+
+/// Condition variable for the crate's Mutex, see Monitor for one that comes with its mutex
+pub struct Condvar {
+    /// Bumped on every notify, waiters sleep on it after unlocking the mutex
+    counter: AtomicU32,
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self {
+            counter: AtomicU32::new(0),
+        }
+    }
+
+    /// Unlocks the guard's mutex, sleeps until notified, and locks it again
+    /// Can wake up spuriously, so callers re-check their condition in a loop
+    /// Err if the mutex got poisoned in the meantime, like Mutex::lock
+    pub fn wait<'a, T, B: BackoffPolicy>(
+        &self,
+        guard: MutexGuard<'a, T, B>,
+    ) -> LockResult<MutexGuard<'a, T, B>> {
+        let mutex = MutexGuard::mutex(&guard);
+        // Read while still holding the lock: a notify for any change made after we unlock bumps it,
+        // so the wait below returns straight away instead of missing the wake up
+        let counter = self.counter.load(Ordering::Relaxed);
+        drop(guard);
+        wait(&self.counter, counter);
+        mutex.lock()
+    }
+
+    /// wait that gives up with Cancelled once `token` is cancelled, leaving the mutex unlocked
+    pub fn wait_cancellable<'a, T, B: BackoffPolicy>(
+        &self,
+        guard: MutexGuard<'a, T, B>,
+        token: &CancellationToken,
+    ) -> Result<LockResult<MutexGuard<'a, T, B>>, Cancelled> {
+        let mutex = MutexGuard::mutex(&guard);
+        let counter = self.counter.load(Ordering::Relaxed);
+        drop(guard);
+        // The counter only ever grows, which is what the token's wake up needs
+        token.wait(&self.counter, counter)?;
+        Ok(mutex.lock())
+    }
+
+    pub fn notify_one(&self) {
+        self.counter.fetch_add(1, Ordering::Relaxed);
+        wake_one(&self.counter);
+    }
+
+    pub fn notify_all(&self) {
+        self.counter.fetch_add(1, Ordering::Relaxed);
+        wake_all(&self.counter);
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, thread, time::Duration};
+
+    use super::*;
+    use crate::mutex::Mutex;
+
+    #[test]
+    fn producer_consumer() {
+        let queue = Mutex::new(VecDeque::new());
+        let not_empty = Condvar::new();
+
+        thread::scope(|s| {
+            let consumer = s.spawn(|| {
+                let mut received = Vec::new();
+                while received.len() < 100 {
+                    let mut q = queue.lock().unwrap();
+                    while q.is_empty() {
+                        q = not_empty.wait(q).unwrap();
+                    }
+                    received.extend(q.drain(..));
+                }
+                received
+            });
+
+            // Give the consumer time to block on the empty queue first
+            thread::sleep(Duration::from_millis(50));
+            for i in 0..100 {
+                queue.lock().unwrap().push_back(i);
+                not_empty.notify_one();
+            }
+
+            assert_eq!(consumer.join().unwrap(), (0..100).collect::<Vec<_>>());
+        });
+    }
+}
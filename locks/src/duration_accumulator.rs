@@ -0,0 +1,68 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Total of durations added from many threads without a lock, e.g. time spent in a concurrent section
+/// Kept as nanoseconds, which saturates after about 584 years
+#[derive(Debug, Default)]
+pub struct DurationAccumulator {
+    nanos: AtomicU64,
+}
+
+impl DurationAccumulator {
+    pub const fn new() -> Self {
+        Self {
+            nanos: AtomicU64::new(0),
+        }
+    }
+
+    pub fn add(&self, d: Duration) {
+        let nanos = u64::try_from(d.as_nanos()).unwrap_or(u64::MAX);
+        // A plain fetch_add would wrap around, saturating needs the CAS loop
+        // Relaxed: only the total matters, it doesn't publish anything else
+        let _ = self
+            .nanos
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |total| {
+                Some(total.saturating_add(nanos))
+            });
+    }
+
+    pub fn total(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::Relaxed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn sums_across_threads() {
+        let accumulator = DurationAccumulator::new();
+
+        thread::scope(|s| {
+            for t in 1..=8 {
+                let accumulator = &accumulator;
+                s.spawn(move || {
+                    for _ in 0..100 {
+                        accumulator.add(Duration::from_micros(t));
+                    }
+                });
+            }
+        });
+
+        // 100 * (1 + 2 + ... + 8) micros
+        assert_eq!(accumulator.total(), Duration::from_micros(3600));
+    }
+
+    #[test]
+    fn saturates() {
+        let accumulator = DurationAccumulator::new();
+        accumulator.add(Duration::MAX);
+        accumulator.add(Duration::from_secs(1));
+        assert_eq!(accumulator.total(), Duration::from_nanos(u64::MAX));
+    }
+}
@@ -1,3 +1,22 @@
+pub mod atomic_cell;
+pub mod backoff;
+pub mod barrier;
+#[cfg(feature = "bench-util")]
+pub mod bench;
+pub mod cancel;
+pub mod checked_spin_lock;
+pub mod condvar;
+pub mod duration_accumulator;
+pub mod lockable;
+pub mod memo;
+pub mod monitor;
 pub mod mutex;
+pub mod notify;
+pub mod once;
+pub mod rate_limiter;
+pub mod registry;
+pub mod ring_log;
 pub mod rwlock;
+pub mod semaphore;
 pub mod spin_lock;
+pub mod wait;
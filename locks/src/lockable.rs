@@ -0,0 +1,98 @@
+use std::ops::Deref;
+
+use crate::{
+    backoff::BackoffPolicy,
+    mutex::{Mutex, MutexGuard},
+    rwlock::{ReadGuard, RwLock, WriteGuard},
+    spin_lock::{Guard, SpinLock},
+};
+
+/// Common interface over the crate's locks, so generic code can swap lock strategies
+/// The guard gives at least shared access to `T`, exclusive locks also hand out DerefMut guards
+/// A poisoned Mutex panics on lock, like Mutex::with
+pub trait Lockable<T> {
+    type Guard<'a>: Deref<Target = T>
+    where
+        Self: 'a;
+
+    fn lock(&self) -> Self::Guard<'_>;
+}
+
+impl<T, B: BackoffPolicy> Lockable<T> for Mutex<T, B> {
+    type Guard<'a>
+        = MutexGuard<'a, T, B>
+    where
+        Self: 'a;
+
+    fn lock(&self) -> Self::Guard<'_> {
+        Mutex::lock(self).expect("mutex poisoned by a panicking holder")
+    }
+}
+
+impl<T, B: BackoffPolicy> Lockable<T> for SpinLock<T, B> {
+    type Guard<'a>
+        = Guard<'a, T, B>
+    where
+        Self: 'a;
+
+    fn lock(&self) -> Self::Guard<'_> {
+        SpinLock::lock(self)
+    }
+}
+
+/// RwLock as a Lockable that takes the read lock, RwLock::read_lockable
+pub struct ReadLock<'l, T>(&'l RwLock<T>);
+
+/// RwLock as a Lockable that takes the write lock, RwLock::write_lockable
+pub struct WriteLock<'l, T>(&'l RwLock<T>);
+
+impl<T> RwLock<T> {
+    pub fn read_lockable(&self) -> ReadLock<'_, T> {
+        ReadLock(self)
+    }
+
+    pub fn write_lockable(&self) -> WriteLock<'_, T> {
+        WriteLock(self)
+    }
+}
+
+impl<'l, T> Lockable<T> for ReadLock<'l, T> {
+    type Guard<'a>
+        = ReadGuard<'l, T>
+    where
+        Self: 'a;
+
+    fn lock(&self) -> Self::Guard<'_> {
+        self.0.read()
+    }
+}
+
+impl<'l, T> Lockable<T> for WriteLock<'l, T> {
+    type Guard<'a>
+        = WriteGuard<'l, T>
+    where
+        Self: 'a;
+
+    fn lock(&self) -> Self::Guard<'_> {
+        self.0.write()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bump<L: Lockable<u64>>(l: &L) -> u64 {
+        *l.lock() + 1
+    }
+
+    #[test]
+    fn bump_each_lock() {
+        assert_eq!(bump(&Mutex::new(41)), 42);
+        assert_eq!(bump(&SpinLock::new(41)), 42);
+
+        let rwlock = RwLock::new(41);
+        assert_eq!(bump(&rwlock.read_lockable()), 42);
+        assert_eq!(bump(&rwlock.write_lockable()), 42);
+    }
+}
@@ -0,0 +1,84 @@
+use std::{
+    collections::HashMap,
+    hash::{BuildHasher, Hash, RandomState},
+    sync::{Arc, OnceLock},
+};
+
+use crate::mutex::Mutex;
+
+const SHARDS: usize = 16;
+
+/// Thread-safe memoization: computes the value for each key at most once
+pub struct Memo<K, V> {
+    /// Keys are spread over several mutexes so lookups for different keys rarely contend
+    /// The mutex is only held to find the key's cell, never while computing a value
+    shards: [Mutex<HashMap<K, Arc<OnceLock<V>>>>; SHARDS],
+    hasher: RandomState,
+}
+
+impl<K: Hash + Eq, V: Clone> Memo<K, V> {
+    pub fn new() -> Self {
+        Self {
+            shards: std::array::from_fn(|_| Mutex::new(HashMap::new())),
+            hasher: RandomState::new(),
+        }
+    }
+
+    /// Returns the value cached for `key`, computing it with `f` on first access
+    /// Concurrent first accesses for the same key block until the single call to `f` finishes
+    pub fn get_or_compute<F: FnOnce() -> V>(&self, key: K, f: F) -> V {
+        let shard = &self.shards[self.hasher.hash_one(&key) as usize % SHARDS];
+        let cell = shard.with(|map| map.entry(key).or_default().clone());
+
+        cell.get_or_init(f).clone()
+    }
+}
+
+impl<K: Hash + Eq, V: Clone> Default for Memo<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+        time::Duration,
+    };
+
+    use super::*;
+
+    #[test]
+    fn computes_once_per_key() {
+        let memo: &'static Memo<usize, String> = Box::leak(Box::new(Memo::new()));
+        let calls: &'static [AtomicUsize; 4] = Box::leak(Box::default());
+
+        let threads: Vec<_> = (0..16)
+            .map(|_| {
+                thread::spawn(|| {
+                    for _ in 0..10 {
+                        for (key, call) in calls.iter().enumerate() {
+                            let value = memo.get_or_compute(key, || {
+                                call.fetch_add(1, Ordering::Relaxed);
+                                // Widen the window for concurrent first accesses
+                                thread::sleep(Duration::from_millis(10));
+                                format!("value {key}")
+                            });
+                            assert_eq!(value, format!("value {key}"));
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        for call in calls {
+            assert_eq!(call.load(Ordering::Relaxed), 1);
+        }
+    }
+}
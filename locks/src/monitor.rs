@@ -0,0 +1,104 @@
+use crate::{
+    cancel::{CancellationToken, Cancelled},
+    condvar::Condvar,
+    mutex::{Mutex, MutexGuard},
+};
+
+/// Mutex bundled with the condition variable that goes with it,
+/// so a wait can't accidentally be paired with the wrong mutex
+/// Panics if the mutex was poisoned by a panicking holder
+pub struct Monitor<T> {
+    mutex: Mutex<T>,
+    condvar: Condvar,
+}
+
+impl<T> Monitor<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            mutex: Mutex::new(value),
+            condvar: Condvar::new(),
+        }
+    }
+
+    /// Locks, then keeps waiting for a notification while `cond` holds
+    /// Returns the guard with `cond` false, e.g. lock_and_wait_while(|queue| queue.is_empty())
+    pub fn lock_and_wait_while<F: Fn(&T) -> bool>(&self, cond: F) -> MutexGuard<'_, T> {
+        let mut guard = self.mutex.lock().unwrap();
+        while cond(&guard) {
+            guard = self.condvar.wait(guard).unwrap();
+        }
+        guard
+    }
+
+    /// lock_and_wait_while that gives up with Cancelled once `token` is cancelled
+    pub fn lock_and_wait_while_cancellable<F: Fn(&T) -> bool>(
+        &self,
+        cond: F,
+        token: &CancellationToken,
+    ) -> Result<MutexGuard<'_, T>, Cancelled> {
+        let mut guard = self.mutex.lock().unwrap();
+        while cond(&guard) {
+            guard = self.condvar.wait_cancellable(guard, token)?.unwrap();
+        }
+        Ok(guard)
+    }
+
+    pub fn notify_one(&self) {
+        self.condvar.notify_one()
+    }
+
+    pub fn notify_all(&self) {
+        self.condvar.notify_all()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, thread, time::Duration};
+
+    use super::*;
+
+    const CAPACITY: usize = 4;
+
+    #[test]
+    fn bounded_buffer() {
+        let buffer = Monitor::new(VecDeque::new());
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..1000 {
+                    let mut queue = buffer.lock_and_wait_while(|q| q.len() == CAPACITY);
+                    queue.push_back(i);
+                    drop(queue);
+                    // Producers and consumers share the monitor, so wake both kinds
+                    buffer.notify_all();
+                }
+            });
+
+            for i in 0..1000 {
+                let mut queue = buffer.lock_and_wait_while(|q| q.is_empty());
+                assert!(queue.len() <= CAPACITY);
+                assert_eq!(queue.pop_front(), Some(i));
+                drop(queue);
+                buffer.notify_all();
+            }
+        });
+    }
+
+    #[test]
+    fn cancel_blocked_wait() {
+        let monitor = Monitor::new(Vec::<u32>::new());
+        let token = CancellationToken::new();
+
+        thread::scope(|s| {
+            let waiter = s.spawn(|| {
+                monitor
+                    .lock_and_wait_while_cancellable(|v| v.is_empty(), &token)
+                    .map(|_| ())
+            });
+            thread::sleep(Duration::from_millis(50));
+            token.cancel();
+            assert_eq!(waiter.join().unwrap(), Err(Cancelled));
+        });
+    }
+}
@@ -1,83 +1,421 @@
 use std::{
     cell::UnsafeCell,
+    error::Error,
+    fmt,
+    hint::spin_loop,
     ops::{Deref, DerefMut},
-    sync::atomic::{AtomicU32, Ordering},
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
     thread,
+    time::{Duration, Instant},
 };
 
-use atomic_wait::{wait, wake_one};
+use atomic_wait::{wait, wake_all, wake_one};
 
-const SPIN_LOCK_N: u32 = 100;
+use crate::{
+    backoff::{BackoffPolicy, ExponentialBackoff, NoSpin, SPIN_ATTEMPTS, SPIN_MAX_SHIFT},
+    wait::wait_timeout,
+};
+
+/// Prints a lock event with the current thread's id, only with the trace-locks feature
+/// Without it the call compiles away, so the lock paths don't format or touch stdout
+macro_rules! trace {
+    ($event:literal) => {
+        #[cfg(feature = "trace-locks")]
+        println!("thread {:?}: {}", thread::current().id(), $event);
+    };
+}
+
+/// Starting point and bounds for the spin budget of LockPolicy::Adaptive
+const ADAPTIVE_START_SPIN: u32 = 100;
+const ADAPTIVE_MIN_SPIN: u32 = 4;
+const ADAPTIVE_MAX_SPIN: u32 = 1000;
 
-pub struct Mutex<T> {
+/// How a contended Mutex picks its next owner
+///
+/// - Throughput: unlocking frees the lock for whoever grabs it first, spinning threads and the
+///   thread that just unlocked included. Cheapest handoff, but a sleeping waiter can be overtaken again and again
+/// - Fair: threads take a ticket and get the lock strictly in arrival order, so no one is overtaken.
+///   Nobody spins, and a contended unlock wakes every sleeper so the next ticket holder can find out it's their turn
+/// - Adaptive: Throughput, but the spin phase grows while spinning keeps winning the lock and
+///   shrinks while it keeps ending in a sleep, instead of following the backoff policy
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LockPolicy {
+    #[default]
+    Throughput,
+    Fair,
+    Adaptive,
+}
+
+pub struct Mutex<T, B = ExponentialBackoff> {
     /// 0 - Unlocked
     /// 1 - Locked
     /// 2 - Threads waiting to Lock
+    /// Unused with LockPolicy::Fair, which locks through the tickets instead
     pub state: AtomicU32,
     value: UnsafeCell<T>,
+    /// Decides how long to spin before sleeping on a contended lock
+    backoff: B,
+    policy: LockPolicy,
+    /// LockPolicy::Fair: next ticket to hand out, and the ticket that currently owns the lock
+    next_ticket: AtomicU32,
+    now_serving: AtomicU32,
+    /// LockPolicy::Adaptive: attempts to spin before sleeping, retuned on every contended lock
+    spin_budget: AtomicU32,
+    /// Threads currently asleep (or about to sleep) on the state, see Mutex::waiters
+    waiters: AtomicU32,
+    /// Set when a guard is dropped while its thread panics, the value may be half updated
+    poisoned: AtomicBool,
 }
 
 /// Sync for Mutex because we want the mutex to be shared amongst threads,
 /// where T: Send because the maximum one thread will have exclusive access to T
-unsafe impl<T> Sync for Mutex<T> where T: Send {}
+unsafe impl<T, B> Sync for Mutex<T, B>
+where
+    T: Send,
+    B: Sync,
+{
+}
+
+impl<T> Mutex<T> {
+    pub fn new(value: T) -> Self {
+        Self::with_backoff(
+            value,
+            ExponentialBackoff {
+                limit: SPIN_ATTEMPTS,
+                max_shift: SPIN_MAX_SHIFT,
+            },
+        )
+    }
+
+    /// Mutex with the default backoff, handing the lock over according to `policy`
+    pub fn new_with_policy(value: T, policy: LockPolicy) -> Self {
+        Mutex {
+            policy,
+            ..Self::new(value)
+        }
+    }
+}
+
+impl<T> Mutex<T, NoSpin> {
+    /// Mutex that never spins: a contended lock goes straight to sleeping on the futex
+    /// Slower handoffs, but no CPU burnt busy waiting, e.g. on battery powered or oversubscribed machines
+    pub fn new_no_spin(value: T) -> Self {
+        Self::with_backoff(value, NoSpin)
+    }
+}
 
 /// Check if its already locked, call wait
 /// If unlocked, lock + return guard
 /// state 0 -> state 1
 /// state 1 -> state 2 + wait
 /// state 2 -> state 2 + wait
-impl<T> Mutex<T> {
-    pub fn new(value: T) -> Self {
+impl<T, B: BackoffPolicy> Mutex<T, B> {
+    pub fn with_backoff(value: T, backoff: B) -> Self {
         Mutex {
             state: AtomicU32::new(0),
             value: UnsafeCell::new(value),
+            backoff,
+            policy: LockPolicy::Throughput,
+            next_ticket: AtomicU32::new(0),
+            now_serving: AtomicU32::new(0),
+            spin_budget: AtomicU32::new(ADAPTIVE_START_SPIN),
+            waiters: AtomicU32::new(0),
+            poisoned: AtomicBool::new(false),
         }
     }
 
-    pub fn lock(&self) -> MutexGuard<T> {
-        if self
+    /// Err if a previous holder panicked while holding the lock, the lock is held either way
+    /// and PoisonError::into_inner still hands out the guard
+    pub fn lock(&self) -> LockResult<MutexGuard<'_, T, B>> {
+        self.acquire();
+        let guard = MutexGuard { lock: self };
+        if self.is_poisoned() {
+            return Err(PoisonError { guard });
+        }
+        Ok(guard)
+    }
+
+    fn acquire(&self) {
+        if self.policy == LockPolicy::Fair {
+            self.lock_fair();
+        } else if self
             .state
             .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
             .is_err()
         {
             // Spin lock before Syscall,
             // Great for situations where lock is not held for long
-            for _ in 0..SPIN_LOCK_N {
-                if self
-                    .state
-                    .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
-                    .is_ok()
-                {
-                    return MutexGuard { lock: self };
-                }
+            if self.spin() {
+                return;
             }
 
             while self.state.swap(2, Ordering::Acquire) != 0 {
-                wait(&self.state, 2)
+                // SeqCst pairs with unlock: either it sees this waiter, or the wait sees the unlocked state
+                self.waiters.fetch_add(1, Ordering::SeqCst);
+                wait(&self.state, 2);
+                self.waiters.fetch_sub(1, Ordering::Relaxed);
             }
         }
+    }
+
+    /// Retries the lock before falling back to a syscall, returns true if it was acquired
+    fn spin(&self) -> bool {
+        let try_lock = || {
+            self.state
+                .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        };
 
-        MutexGuard { lock: self }
+        if self.policy == LockPolicy::Adaptive {
+            // Racy read-modify-write of the budget is fine, it's only a hint
+            let budget = self.spin_budget.load(Ordering::Relaxed);
+            for _ in 0..budget {
+                if try_lock() {
+                    let grown = (budget * 2).min(ADAPTIVE_MAX_SPIN);
+                    self.spin_budget.store(grown, Ordering::Relaxed);
+                    return true;
+                }
+                spin_loop();
+            }
+            let shrunk = (budget / 2).max(ADAPTIVE_MIN_SPIN);
+            self.spin_budget.store(shrunk, Ordering::Relaxed);
+            return false;
+        }
+
+        let mut attempt = 0;
+        while self.backoff.should_spin(attempt) {
+            if try_lock() {
+                return true;
+            }
+            attempt += 1;
+        }
+        false
     }
 
-    pub fn unlock(&self) {
-        // If state was = 2, we know other threads are waiting, wake one up
-        let tid = thread::current().id();
-        println!("thread {:?}: unlocking", tid);
-        if self.state.swap(0, Ordering::Release) == 2 {
-            println!("thread {:?}: waking one", tid);
-            wake_one(&self.state)
+    /// Ticket lock: wait until our ticket is the one being served
+    fn lock_fair(&self) {
+        // SeqCst so unlock can't miss this ticket when deciding whether to wake anyone, see unlock
+        let ticket = self.next_ticket.fetch_add(1, Ordering::SeqCst);
+        loop {
+            let serving = self.now_serving.load(Ordering::SeqCst);
+            if serving == ticket {
+                return;
+            }
+            wait(&self.now_serving, serving)
+        }
+    }
+
+    /// Like lock, but gives up and returns None once `dur` has passed without getting the lock
+    pub fn lock_timeout(&self, dur: Duration) -> Option<MutexGuard<'_, T, B>> {
+        let deadline = Instant::now() + dur;
+
+        if self.policy == LockPolicy::Fair {
+            // A ticket can't be handed back, so poll instead of queueing
+            // Slices are short because unlock only wakes sleepers when a ticket is queued
+            loop {
+                if let Some(guard) = self.try_lock() {
+                    return Some(guard);
+                }
+                let serving = self.now_serving.load(Ordering::SeqCst);
+                let remaining = deadline.checked_duration_since(Instant::now())?;
+                wait_timeout(
+                    &self.now_serving,
+                    serving,
+                    remaining.min(Duration::from_millis(1)),
+                );
+            }
+        }
+
+        if let Some(guard) = self.try_lock() {
+            return Some(guard);
+        }
+        if self.spin() {
+            return Some(MutexGuard { lock: self });
+        }
+
+        // Same as lock's slow path, with the sleep bounded by the time left
+        // Giving up leaves the state at 2 at worst, which only costs the holder's next lock a swap:
+        // unlock decides whether to wake anyone from the waiter count, not from the state
+        while self.state.swap(2, Ordering::Acquire) != 0 {
+            let remaining = deadline.checked_duration_since(Instant::now())?;
+            self.waiters.fetch_add(1, Ordering::SeqCst);
+            wait_timeout(&self.state, 2, remaining);
+            self.waiters.fetch_sub(1, Ordering::Relaxed);
+        }
+
+        Some(MutexGuard { lock: self })
+    }
+
+    /// Locks only if the mutex is free right now, never spins or sleeps
+    /// A failed attempt leaves the state alone, so it doesn't look like contention to the holder's unlock
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T, B>> {
+        let locked = if self.policy == LockPolicy::Fair {
+            // Free means no ticket is out past the one being served, take the next one only in that case
+            let serving = self.now_serving.load(Ordering::SeqCst);
+            self.next_ticket
+                .compare_exchange(
+                    serving,
+                    serving.wrapping_add(1),
+                    Ordering::SeqCst,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+        } else {
+            self.state
+                .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        };
+
+        locked.then(|| MutexGuard { lock: self })
+    }
+
+    /// Locks the mutex, runs `f` with exclusive access to the value and unlocks before returning
+    /// Keeps the critical section scoped to the closure, so the guard can't be held across unrelated work
+    /// Panics if the mutex is poisoned, use lock to recover from that
+    pub fn with<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+        let mut guard = self.lock().expect("mutex poisoned by a panicking holder");
+        f(&mut guard)
+    }
+}
+
+impl<T, B> Mutex<T, B> {
+    /// Takes the value out, owning the mutex means nobody can hold the lock
+    /// Mutex has no Drop of its own, so the cell is simply moved out of and the value dropped once, by the caller
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    /// &mut to the value without locking, `&mut self` already rules out any other access
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    /// Whether a holder panicked, try_lock and lock_timeout don't report it so check here
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::Relaxed)
+    }
+
+    /// Rough number of threads queued on the lock, e.g. for load shedding
+    /// Only a snapshot, it can be stale by the time the caller acts on it
+    pub fn waiters(&self) -> u32 {
+        if self.policy == LockPolicy::Fair {
+            // Every ticket handed out past the one being served is queued
+            let next = self.next_ticket.load(Ordering::Relaxed);
+            let serving = self.now_serving.load(Ordering::Relaxed);
+            next.wrapping_sub(serving).saturating_sub(1)
+        } else {
+            self.waiters.load(Ordering::Relaxed)
+        }
+    }
+
+    /// Unlocks the mutex without a guard, e.g. when a guard was leaked through mem::forget
+    /// Wakes a waiter just like dropping the guard would
+    ///
+    /// # Safety
+    /// No guard of this mutex may still be alive, or its holder keeps using the value while another
+    /// thread locks it. Only meant as an escape hatch, e.g. recovering a leaked lock during a controlled shutdown
+    pub unsafe fn force_unlock(&self) {
+        self.unlock()
+    }
+
+    /// Private, so force_unlock is the only way to unlock without a guard
+    fn unlock(&self) {
+        // If any thread registered as a waiter, wake one up
+        trace!("unlocking");
+        if self.policy == LockPolicy::Fair {
+            let serving = self
+                .now_serving
+                .fetch_add(1, Ordering::SeqCst)
+                .wrapping_add(1);
+            // Only wake sleepers if a later ticket was handed out
+            // Both sides being SeqCst means either we see the ticket here, or its owner sees the new serving value
+            if self.next_ticket.load(Ordering::SeqCst) != serving {
+                trace!("waking all");
+                wake_all(&self.now_serving)
+            }
+        } else {
+            self.state.swap(0, Ordering::SeqCst);
+            // Only pay for the syscall if someone is actually asleep
+            if self.waiters.load(Ordering::SeqCst) > 0 {
+                trace!("waking one");
+                wake_one(&self.state)
+            }
         }
     }
 }
 
 /// Mutex::lock -> MutexGuard
-pub(crate) struct MutexGuard<'a, T> {
-    lock: &'a Mutex<T>,
+///
+/// Sharing a guard shares &T, so the guard is only Sync when T is
+/// ```compile_fail,E0277
+/// use std::cell::Cell;
+/// use locks::mutex::MutexGuard;
+///
+/// fn assert_sync<T: Sync>() {}
+/// assert_sync::<MutexGuard<'static, Cell<u32>>>();
+/// ```
+pub struct MutexGuard<'a, T, B = ExponentialBackoff> {
+    lock: &'a Mutex<T, B>,
+}
+
+/// Would be Sync through &Mutex with only T: Send, which lets two threads share &T
+unsafe impl<T: Sync, B: Sync> Sync for MutexGuard<'_, T, B> {}
+
+impl<'a, T, B> MutexGuard<'a, T, B> {
+    /// The mutex this guard holds, e.g. for Condvar::wait to relock it
+    pub(crate) fn mutex(guard: &Self) -> &'a Mutex<T, B> {
+        guard.lock
+    }
+
+    /// Gives up mutable access while keeping the mutex locked, e.g. before handing the value to code that should only read it
+    ///
+    /// ```
+    /// use locks::mutex::{Mutex, MutexGuard};
+    ///
+    /// let mutex = Mutex::new(vec![1]);
+    /// let mut guard = mutex.lock().unwrap();
+    /// guard.push(2);
+    /// let view = MutexGuard::into_readonly(guard);
+    /// assert_eq!(*view, [1, 2]);
+    /// ```
+    ///
+    /// ```compile_fail,E0596
+    /// use locks::mutex::{Mutex, MutexGuard};
+    ///
+    /// let mutex = Mutex::new(vec![1]);
+    /// let mut view = MutexGuard::into_readonly(mutex.lock().unwrap());
+    /// view.push(2);
+    /// ```
+    pub fn into_readonly(guard: Self) -> ReadOnlyGuard<'a, T, B> {
+        let lock = guard.lock;
+        // The read only guard takes over unlocking
+        std::mem::forget(guard);
+        ReadOnlyGuard { lock }
+    }
+
+    /// Narrows the guard down to part of the value, the mutex stays locked until the mapped guard drops
+    ///
+    /// ```
+    /// use locks::mutex::{Mutex, MutexGuard};
+    ///
+    /// let mutex = Mutex::new((1, vec![1]));
+    /// let mut list = MutexGuard::map(mutex.lock().unwrap(), |(_, list)| list);
+    /// list.push(2);
+    /// drop(list);
+    /// assert_eq!(mutex.lock().unwrap().1, [1, 2]);
+    /// ```
+    pub fn map<U, F: FnOnce(&mut T) -> &mut U>(mut guard: Self, f: F) -> MappedMutexGuard<'a, U> {
+        let lock = guard.lock;
+        // If f panics the original guard still unlocks, and poisons
+        let value: *mut U = f(&mut guard);
+        // The mapped guard takes over unlocking
+        std::mem::forget(guard);
+        MappedMutexGuard { lock, value }
+    }
 }
 
 /// Deref to &T
-impl<T> Deref for MutexGuard<'_, T> {
+impl<T, B> Deref for MutexGuard<'_, T, B> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
         unsafe { &*self.lock.value.get() }
@@ -85,41 +423,151 @@ impl<T> Deref for MutexGuard<'_, T> {
 }
 
 /// DerefMut to &mut T
-impl<T> DerefMut for MutexGuard<'_, T> {
+impl<T, B> DerefMut for MutexGuard<'_, T, B> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         unsafe { &mut *self.lock.value.get() }
     }
 }
 
-/// Dropping guard -> unlocks mutex
-impl<T> Drop for MutexGuard<'_, T> {
+/// Dropping guard -> unlocks mutex, poisoning it first if the thread is panicking
+impl<T, B> Drop for MutexGuard<'_, T, B> {
+    fn drop(&mut self) {
+        // Relaxed: the unlock right after publishes it to the next holder
+        if thread::panicking() {
+            self.lock.poisoned.store(true, Ordering::Relaxed);
+        }
+        self.lock.unlock();
+        trace!("dropping lock");
+    }
+}
+
+/// Mutex::lock's result, Err when the mutex is poisoned
+pub type LockResult<G> = Result<G, PoisonError<G>>;
+
+/// A holder panicked, so the value may be half updated. Still holds the lock
+pub struct PoisonError<G> {
+    guard: G,
+}
+
+impl<G> PoisonError<G> {
+    /// The guard, for callers that can deal with a possibly inconsistent value
+    pub fn into_inner(self) -> G {
+        self.guard
+    }
+}
+
+impl<G> fmt::Debug for PoisonError<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PoisonError").finish_non_exhaustive()
+    }
+}
+
+impl<G> fmt::Display for PoisonError<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("mutex poisoned by a panicking holder")
+    }
+}
+
+impl<G> Error for PoisonError<G> {}
+
+/// MutexGuard::into_readonly -> ReadOnlyGuard, still holds the lock but only derefs to &T
+///
+/// ```compile_fail,E0277
+/// use std::cell::Cell;
+/// use locks::mutex::ReadOnlyGuard;
+///
+/// fn assert_sync<T: Sync>() {}
+/// assert_sync::<ReadOnlyGuard<'static, Cell<u32>>>();
+/// ```
+pub struct ReadOnlyGuard<'a, T, B = ExponentialBackoff> {
+    lock: &'a Mutex<T, B>,
+}
+
+/// Same as MutexGuard
+unsafe impl<T: Sync, B: Sync> Sync for ReadOnlyGuard<'_, T, B> {}
+
+impl<T, B> Deref for ReadOnlyGuard<'_, T, B> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T, B> Drop for ReadOnlyGuard<'_, T, B> {
+    fn drop(&mut self) {
+        // Can't modify the value anymore, but may have been converted from a half finished update
+        if thread::panicking() {
+            self.lock.poisoned.store(true, Ordering::Relaxed);
+        }
+        self.lock.unlock();
+    }
+}
+
+/// MutexGuard::map -> MappedMutexGuard, still holds the lock but only derefs to part of the value
+/// Keeps the mutex behind a dyn Unlock, so the type doesn't carry the full value's T around
+pub struct MappedMutexGuard<'a, U> {
+    lock: &'a dyn Unlock,
+    /// Points into the locked value, valid until this guard unlocks
+    value: *mut U,
+}
+
+impl<U> Deref for MappedMutexGuard<'_, U> {
+    type Target = U;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.value }
+    }
+}
+
+impl<U> DerefMut for MappedMutexGuard<'_, U> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.value }
+    }
+}
+
+impl<U> Drop for MappedMutexGuard<'_, U> {
     fn drop(&mut self) {
+        if thread::panicking() {
+            self.lock.poison();
+        }
         self.lock.unlock();
-        println!("thread: {:?}, dropping lock ", std::thread::current().id());
+    }
+}
+
+/// Unlocking a mutex without knowing its T, for MappedMutexGuard
+trait Unlock {
+    fn unlock(&self);
+    fn poison(&self);
+}
+
+impl<T, B> Unlock for Mutex<T, B> {
+    fn unlock(&self) {
+        Mutex::unlock(self);
+    }
+
+    fn poison(&self) {
+        self.poisoned.store(true, Ordering::Relaxed);
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::thread;
+    use std::{
+        sync::atomic::Ordering,
+        thread,
+        time::{Duration, Instant},
+    };
 
-    use super::Mutex;
+    use super::{LockPolicy, Mutex, MutexGuard};
+    use crate::backoff::{BackoffPolicy, ExponentialBackoff, FixedSpin, NoSpin};
 
     #[test]
     fn to_100000() {
-        println!("running mutex test");
         let mutex: &'static _ = Box::leak(Box::new(Mutex::new(0)));
         let mut threads = Vec::new();
         for _ in 0..10 {
             let t = thread::spawn(|| {
                 for _ in 0..10000 {
-                    println!(
-                        "thread: {:?}, attempting to acquire lock. state: {:?}",
-                        std::thread::current().id(),
-                        mutex.state
-                    );
-                    let mut guard = mutex.lock();
-                    println!("thread: {:?}, currently has lock", thread::current().id());
+                    let mut guard = mutex.lock().unwrap();
                     *guard += 1
                 }
             });
@@ -132,6 +580,294 @@ mod tests {
             t.join().unwrap();
         }
 
-        assert_eq!(100000, *mutex.lock())
+        assert_eq!(100000, *mutex.lock().unwrap())
+    }
+
+    #[test]
+    fn with_to_100000() {
+        let mutex: &'static _ = Box::leak(Box::new(Mutex::new(0)));
+        let mut threads = Vec::new();
+        for _ in 0..10 {
+            threads.push(thread::spawn(|| {
+                for _ in 0..10000 {
+                    mutex.with(|v| *v += 1);
+                }
+            }));
+        }
+
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        assert_eq!(100000, mutex.with(|v| *v))
+    }
+
+    fn count_with<B: BackoffPolicy + Sync + 'static>(backoff: B) {
+        let mutex: &'static _ = Box::leak(Box::new(Mutex::with_backoff(0, backoff)));
+        let threads: Vec<_> = (0..10)
+            .map(|_| {
+                thread::spawn(|| {
+                    for _ in 0..1000 {
+                        *mutex.lock().unwrap() += 1;
+                    }
+                })
+            })
+            .collect();
+
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        assert_eq!(10000, *mutex.lock().unwrap())
+    }
+
+    #[test]
+    fn default_backoff_under_contention() {
+        let mutex = Mutex::new(0);
+        thread::scope(|s| {
+            for _ in 0..16 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        *mutex.lock().unwrap() += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(*mutex.lock().unwrap(), 16000);
+    }
+
+    #[test]
+    fn backoff_policies() {
+        count_with(FixedSpin(10));
+        count_with(ExponentialBackoff::default());
+        count_with(NoSpin);
+    }
+
+    #[test]
+    fn no_spin_to_100000() {
+        let mutex: &'static _ = Box::leak(Box::new(Mutex::new_no_spin(0)));
+        let threads: Vec<_> = (0..10)
+            .map(|_| {
+                thread::spawn(|| {
+                    for _ in 0..10000 {
+                        *mutex.lock().unwrap() += 1;
+                    }
+                })
+            })
+            .collect();
+
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        assert_eq!(100000, *mutex.lock().unwrap())
+    }
+
+    #[test]
+    fn lock_policies_to_100000() {
+        for policy in [
+            LockPolicy::Throughput,
+            LockPolicy::Fair,
+            LockPolicy::Adaptive,
+        ] {
+            let mutex: &'static _ = Box::leak(Box::new(Mutex::new_with_policy(0, policy)));
+            let threads: Vec<_> = (0..10)
+                .map(|_| {
+                    thread::spawn(|| {
+                        for _ in 0..10000 {
+                            *mutex.lock().unwrap() += 1;
+                        }
+                    })
+                })
+                .collect();
+
+            for t in threads {
+                t.join().unwrap();
+            }
+
+            assert_eq!(100000, *mutex.lock().unwrap(), "{policy:?}");
+        }
+    }
+
+    #[test]
+    fn fair_policy_serves_in_ticket_order() {
+        const THREADS: usize = 8;
+        let mutex = Mutex::new_with_policy(Vec::new(), LockPolicy::Fair);
+        let held = mutex.lock().unwrap();
+
+        thread::scope(|s| {
+            for i in 0..THREADS {
+                let mutex = &mutex;
+                s.spawn(move || mutex.lock().unwrap().push(i));
+                // Thread i has taken its ticket before the next one is spawned
+                while mutex.waiters() != i as u32 + 1 {
+                    thread::yield_now();
+                }
+            }
+            drop(held);
+        });
+
+        // Nobody overtook anyone queued before them
+        assert_eq!(*mutex.lock().unwrap(), (0..THREADS).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn waiters_rise_and_drain() {
+        for policy in [LockPolicy::Throughput, LockPolicy::Fair] {
+            let mutex: &'static _ = Box::leak(Box::new(Mutex::new_with_policy(0, policy)));
+            let guard = mutex.lock().unwrap();
+            assert_eq!(mutex.waiters(), 0);
+
+            let threads: Vec<_> = (0..3)
+                .map(|_| thread::spawn(|| *mutex.lock().unwrap() += 1))
+                .collect();
+
+            // Waiters only register once they're done spinning
+            while mutex.waiters() != 3 {
+                thread::yield_now();
+            }
+
+            drop(guard);
+            for t in threads {
+                t.join().unwrap();
+            }
+            assert_eq!(mutex.waiters(), 0, "{policy:?}");
+            assert_eq!(*mutex.lock().unwrap(), 3);
+        }
+    }
+
+    #[test]
+    fn readonly_guard_keeps_lock() {
+        let mutex = Mutex::new(0);
+        let mut guard = mutex.lock().unwrap();
+        *guard += 1;
+
+        let view = MutexGuard::into_readonly(guard);
+        assert_eq!(*view, 1);
+        assert_ne!(mutex.state.load(Ordering::Relaxed), 0);
+
+        drop(view);
+        assert_eq!(mutex.state.load(Ordering::Relaxed), 0);
+        assert_eq!(*mutex.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn mapped_guard_keeps_lock() {
+        let mutex = Mutex::new((1u32, String::from("a")));
+
+        let mut name = MutexGuard::map(mutex.lock().unwrap(), |(_, name)| name);
+        name.push('b');
+        assert!(mutex.try_lock().is_none());
+
+        drop(name);
+        assert_eq!(mutex.state.load(Ordering::Relaxed), 0);
+        assert_eq!(*mutex.lock().unwrap(), (1, String::from("ab")));
+    }
+
+    #[test]
+    fn try_lock_while_held() {
+        for policy in [LockPolicy::Throughput, LockPolicy::Fair] {
+            let mutex = Mutex::new_with_policy(0, policy);
+            let guard = mutex.lock().unwrap();
+
+            thread::scope(|s| {
+                s.spawn(|| assert!(mutex.try_lock().is_none()));
+            });
+            // The failed attempt didn't mark the lock as contended
+            assert_eq!(mutex.waiters(), 0);
+            if policy == LockPolicy::Throughput {
+                assert_eq!(mutex.state.load(Ordering::Relaxed), 1);
+            }
+
+            drop(guard);
+            thread::scope(|s| {
+                s.spawn(|| *mutex.try_lock().unwrap() += 1);
+            });
+            assert_eq!(*mutex.lock().unwrap(), 1);
+        }
+    }
+
+    #[test]
+    fn lock_timeout_gives_up() {
+        for policy in [LockPolicy::Throughput, LockPolicy::Fair] {
+            let mutex = Mutex::new_with_policy(0, policy);
+
+            thread::scope(|s| {
+                let guard = mutex.lock().unwrap();
+                s.spawn(move || {
+                    thread::sleep(Duration::from_millis(200));
+                    drop(guard);
+                });
+
+                let start = Instant::now();
+                assert!(mutex.lock_timeout(Duration::from_millis(50)).is_none());
+                assert!(start.elapsed() >= Duration::from_millis(50));
+                assert!(start.elapsed() < Duration::from_millis(200));
+
+                *mutex.lock_timeout(Duration::from_secs(10)).unwrap() += 1;
+            });
+
+            // Giving up didn't leave the lock in a state a plain lock can't get through
+            assert_eq!(*mutex.lock().unwrap(), 1);
+            assert_eq!(mutex.waiters(), 0);
+        }
+    }
+
+    #[test]
+    fn into_inner_and_get_mut() {
+        let mut mutex = Mutex::new(String::from("hello"));
+        mutex.get_mut().push_str(", world");
+        mutex.lock().unwrap().push('!');
+
+        assert_eq!(mutex.into_inner(), "hello, world!");
+    }
+
+    #[test]
+    fn normal_unlock_not_poisoned() {
+        let mutex = Mutex::new(0);
+        *mutex.lock().unwrap() += 1;
+        assert!(!mutex.is_poisoned());
+        assert_eq!(*mutex.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn panicking_holder_poisons() {
+        let mutex = Mutex::new(0);
+
+        let result = thread::scope(|s| {
+            s.spawn(|| {
+                let mut guard = mutex.lock().unwrap();
+                *guard += 1;
+                panic!("holder panicked");
+            })
+            .join()
+        });
+        assert!(result.is_err());
+        assert!(mutex.is_poisoned());
+
+        // Still locks, the value is there for whoever accepts the risk
+        let Err(poisoned) = mutex.lock() else {
+            panic!("mutex should be poisoned")
+        };
+        let guard = poisoned.into_inner();
+        assert_eq!(*guard, 1);
+        drop(guard);
+        assert!(mutex.lock().is_err());
+    }
+
+    #[test]
+    fn force_unlock_after_forgotten_guard() {
+        let mutex: &'static _ = Box::leak(Box::new(Mutex::new(0)));
+
+        std::mem::forget(mutex.lock().unwrap());
+        assert_ne!(mutex.state.load(Ordering::Relaxed), 0);
+
+        unsafe { mutex.force_unlock() };
+        assert_eq!(mutex.state.load(Ordering::Relaxed), 0);
+
+        thread::spawn(|| *mutex.lock().unwrap() += 1)
+            .join()
+            .unwrap();
+        assert_eq!(*mutex.lock().unwrap(), 1);
     }
 }
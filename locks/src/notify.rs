@@ -0,0 +1,142 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use atomic_wait::{wait, wake_one};
+
+/// Low 16 bits of the state count the registered waiters
+const WAITER: u32 = 1;
+const WAITER_MASK: u32 = 0xFFFF;
+/// High 16 bits of the state count the notifications not yet consumed
+const NOTIFIED: u32 = 1 << 16;
+
+pub struct Notify {
+    /// waiters | notifications << 16
+    /// A notification with no one to consume it is kept as a single permit,
+    /// so the next call to wait returns immediately instead of missing it
+    state: AtomicU32,
+}
+
+impl Notify {
+    pub fn new() -> Self {
+        Self {
+            state: AtomicU32::new(0),
+        }
+    }
+
+    /// Blocks until a notification is available, consuming it
+    pub fn wait(&self) {
+        // Register before checking for a notification, so notify_one knows a waiter is around
+        let mut s = self.state.fetch_add(WAITER, Ordering::Relaxed) + WAITER;
+
+        loop {
+            if s >> 16 > 0 {
+                match self.state.compare_exchange(
+                    s,
+                    s - NOTIFIED - WAITER,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => return,
+                    Err(e) => {
+                        s = e;
+                        continue;
+                    }
+                }
+            }
+
+            // Any change to the state (new waiter, new notification) makes this return
+            wait(&self.state, s);
+            s = self.state.load(Ordering::Relaxed);
+        }
+    }
+
+    /// Wakes one waiting thread, or stores a permit if no thread is waiting
+    /// Permits don't accumulate: notifying twice with no waiters only releases one future wait
+    pub fn notify_one(&self) {
+        let mut s = self.state.load(Ordering::Relaxed);
+
+        loop {
+            let notified = s >> 16;
+            let waiters = s & WAITER_MASK;
+            // Every waiter already has a notification to consume, or a permit is already stored
+            if notified >= waiters.max(1) {
+                return;
+            }
+
+            match self.state.compare_exchange(
+                s,
+                s + NOTIFIED,
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(e) => s = e,
+            }
+        }
+
+        if s & WAITER_MASK > 0 {
+            wake_one(&self.state);
+        }
+    }
+}
+
+impl Default for Notify {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        thread,
+        time::Duration,
+    };
+
+    use super::*;
+
+    #[test]
+    fn notify_before_wait() {
+        let notify = Notify::new();
+        notify.notify_one();
+        // Permit was stored, so this returns without blocking
+        notify.wait();
+    }
+
+    #[test]
+    fn wait_before_notify() {
+        let notify: &'static _ = Box::leak(Box::new(Notify::new()));
+        let woken: &'static _ = Box::leak(Box::new(AtomicBool::new(false)));
+
+        let t = thread::spawn(|| {
+            notify.wait();
+            woken.store(true, Ordering::Relaxed);
+        });
+
+        thread::sleep(Duration::from_millis(100));
+        assert!(!woken.load(Ordering::Relaxed));
+
+        notify.notify_one();
+        t.join().unwrap();
+        assert!(woken.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn one_notification_per_waiter() {
+        let notify: &'static _ = Box::leak(Box::new(Notify::new()));
+
+        let threads: Vec<_> = (0..4).map(|_| thread::spawn(|| notify.wait())).collect();
+
+        // Notifications are capped by the number of registered waiters
+        while notify.state.load(Ordering::Relaxed) & WAITER_MASK != 4 {
+            thread::yield_now();
+        }
+        for _ in 0..4 {
+            notify.notify_one();
+        }
+
+        for t in threads {
+            t.join().unwrap();
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use atomic_wait::{wait, wake_all};
+
+const INCOMPLETE: u32 = 0;
+const RUNNING: u32 = 1;
+const COMPLETE: u32 = 2;
+
+/// Runs a closure exactly once, no matter how many threads call it
+pub struct Once {
+    /// INCOMPLETE -> RUNNING -> COMPLETE, back to INCOMPLETE if the closure panics
+    state: AtomicU32,
+}
+
+impl Once {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU32::new(INCOMPLETE),
+        }
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) == COMPLETE
+    }
+
+    /// Runs f if no call_once has completed yet, blocks while another thread is running its closure
+    /// If that closure panics, one of the blocked threads runs its own f instead
+    pub fn call_once<F: FnOnce()>(&self, f: F) {
+        loop {
+            match self.state.compare_exchange(
+                INCOMPLETE,
+                RUNNING,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => break,
+                Err(COMPLETE) => return,
+                Err(_) => wait(&self.state, RUNNING),
+            }
+        }
+
+        // Resets the state if f panics, otherwise waiters would sleep forever
+        let reset = Reset(self);
+        f();
+        std::mem::forget(reset);
+
+        self.state.store(COMPLETE, Ordering::Release);
+        wake_all(&self.state);
+    }
+}
+
+impl Default for Once {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct Reset<'a>(&'a Once);
+
+impl Drop for Reset<'_> {
+    fn drop(&mut self) {
+        self.0.state.store(INCOMPLETE, Ordering::Release);
+        wake_all(&self.0.state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{panic, sync::atomic::AtomicUsize, thread};
+
+    use super::*;
+
+    #[test]
+    fn runs_once() {
+        let once = Once::new();
+        let calls = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            for _ in 0..10 {
+                s.spawn(|| {
+                    once.call_once(|| {
+                        calls.fetch_add(1, Ordering::Relaxed);
+                    });
+                    // Nobody gets past call_once before the closure has finished
+                    assert_eq!(calls.load(Ordering::Relaxed), 1);
+                });
+            }
+        });
+
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        assert!(once.is_completed());
+    }
+
+    #[test]
+    fn panic_lets_next_caller_run() {
+        let once = Once::new();
+
+        let result = panic::catch_unwind(|| once.call_once(|| panic!("init failed")));
+        assert!(result.is_err());
+        assert!(!once.is_completed());
+
+        let mut ran = false;
+        once.call_once(|| ran = true);
+        assert!(ran);
+        assert!(once.is_completed());
+    }
+}
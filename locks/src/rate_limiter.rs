@@ -0,0 +1,85 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+/// Lets an action through at most once per interval across all threads, e.g. for throttled logging
+pub struct RateLimiter {
+    interval: u64,
+    /// Time of the last allowed action, in nanos on the limiter's timeline, see now
+    last: AtomicU64,
+    origin: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval: u64::try_from(interval.as_nanos()).unwrap_or(u64::MAX),
+            last: AtomicU64::new(0),
+            origin: Instant::now(),
+        }
+    }
+
+    /// Nanos since the limiter was created, shifted by one interval so the very first try_acquire
+    /// goes through against the initial last of 0
+    fn now(&self) -> u64 {
+        let elapsed = u64::try_from(self.origin.elapsed().as_nanos()).unwrap_or(u64::MAX);
+        elapsed.saturating_add(self.interval)
+    }
+
+    /// True if at least the interval passed since the last action, which then counts as this one
+    /// Only one of the threads racing for the same interval gets true
+    pub fn try_acquire(&self) -> bool {
+        let now = self.now();
+        let last = self.last.load(Ordering::Relaxed);
+        if now.saturating_sub(last) < self.interval {
+            return false;
+        }
+        // Losing the CAS means someone else took this interval
+        // Relaxed: the timestamp is the only thing shared, it doesn't guard other data
+        self.last
+            .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::atomic::AtomicUsize, thread};
+
+    use super::*;
+
+    #[test]
+    fn once_per_interval() {
+        let limiter = RateLimiter::new(Duration::from_millis(20));
+        let successes = AtomicUsize::new(0);
+        let window = Duration::from_millis(200);
+        // Shared, so a thread spawned late doesn't stretch the window
+        let start = Instant::now();
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    while start.elapsed() < window {
+                        if limiter.try_acquire() {
+                            successes.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                });
+            }
+        });
+
+        // One at the start, then one per elapsed interval. Each interval counts from the winning
+        // thread's clock read, so scheduling delays can only push successes later, never add more
+        let successes = successes.load(Ordering::Relaxed);
+        assert!(successes <= 11, "{successes} successes");
+        assert!(successes >= 5, "{successes} successes");
+    }
+
+    #[test]
+    fn first_acquire_succeeds() {
+        let limiter = RateLimiter::new(Duration::from_secs(60));
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+    }
+}
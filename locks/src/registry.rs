@@ -0,0 +1,154 @@
+use std::{
+    ops::Deref,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use crate::{
+    mutex::Mutex,
+    rwlock::{ReadGuard, RwLock},
+};
+
+/// Stable reference to a value in a Registry, only resolves while that exact value is still in its slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle {
+    index: u32,
+    generation: u32,
+}
+
+struct Slot<T> {
+    value: RwLock<Option<T>>,
+    /// Bumped every time the slot's value is removed, so handles to it stop resolving
+    /// Only changed under the slot's write lock
+    generation: AtomicU32,
+}
+
+/// Fixed capacity slot map: values are looked up through generation checked handles,
+/// and a removed value's slot is reused without old handles resolving to the new value
+pub struct Registry<T> {
+    slots: Box<[Slot<T>]>,
+    /// Indices of empty slots
+    free: Mutex<Vec<u32>>,
+}
+
+impl<T> Registry<T> {
+    pub fn with_capacity(capacity: u32) -> Self {
+        Self {
+            slots: (0..capacity)
+                .map(|_| Slot {
+                    value: RwLock::new(None),
+                    generation: AtomicU32::new(0),
+                })
+                .collect(),
+            // Reversed so slots get handed out from index 0 up
+            free: Mutex::new((0..capacity).rev().collect()),
+        }
+    }
+
+    /// Stores `value` in a free slot, panics if the registry is full
+    pub fn insert(&self, value: T) -> Handle {
+        let index = self.free.with(|free| free.pop()).expect("registry is full");
+        let slot = &self.slots[index as usize];
+
+        let mut guard = slot.value.write();
+        *guard = Some(value);
+        Handle {
+            index,
+            generation: slot.generation.load(Ordering::Relaxed),
+        }
+    }
+
+    /// The value behind `handle`, None if it was removed since
+    /// The slot stays read locked while the returned reference is alive, so a remove waits for it
+    pub fn get(&self, handle: Handle) -> Option<Ref<'_, T>> {
+        let slot = self.slots.get(handle.index as usize)?;
+        let guard = slot.value.read();
+        // Checked under the lock, so the value can't be swapped out between the check and the read
+        if slot.generation.load(Ordering::Relaxed) != handle.generation || guard.is_none() {
+            return None;
+        }
+        Some(Ref { guard })
+    }
+
+    /// Takes the value out, invalidating every handle to it
+    pub fn remove(&self, handle: Handle) -> Option<T> {
+        let slot = self.slots.get(handle.index as usize)?;
+        let mut guard = slot.value.write();
+        if slot.generation.load(Ordering::Relaxed) != handle.generation {
+            return None;
+        }
+
+        let value = guard.take()?;
+        slot.generation.fetch_add(1, Ordering::Relaxed);
+        drop(guard);
+
+        self.free.with(|free| free.push(handle.index));
+        Some(value)
+    }
+}
+
+/// Registry::get -> Ref, derefs to the value
+pub struct Ref<'a, T> {
+    guard: ReadGuard<'a, Option<T>>,
+}
+
+impl<T> Deref for Ref<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.guard
+            .as_ref()
+            .expect("Registry::get only hands out occupied slots")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn stale_handle_after_reinsert() {
+        let registry = Registry::with_capacity(1);
+
+        let old = registry.insert("first");
+        assert_eq!(registry.get(old).as_deref(), Some(&"first"));
+
+        assert_eq!(registry.remove(old), Some("first"));
+        assert!(registry.get(old).is_none());
+        assert_eq!(registry.remove(old), None);
+
+        // Same slot, new generation
+        let new = registry.insert("second");
+        assert_eq!(new.index, old.index);
+        assert_ne!(new.generation, old.generation);
+        assert!(registry.get(old).is_none());
+        assert_eq!(registry.get(new).as_deref(), Some(&"second"));
+    }
+
+    #[test]
+    fn shared_across_threads() {
+        let registry = Registry::with_capacity(64);
+
+        let handles: Vec<_> = thread::scope(|s| {
+            let threads: Vec<_> = (0..4)
+                .map(|t| {
+                    let registry = &registry;
+                    s.spawn(move || {
+                        (0..16)
+                            .map(|i| registry.insert(t * 16 + i))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            threads
+                .into_iter()
+                .flat_map(|t| t.join().unwrap())
+                .collect()
+        });
+
+        let mut values: Vec<_> = handles.iter().map(|&h| *registry.get(h).unwrap()).collect();
+        values.sort();
+        assert_eq!(values, (0..64).collect::<Vec<_>>());
+    }
+}
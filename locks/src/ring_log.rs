@@ -0,0 +1,79 @@
+use crate::spin_lock::SpinLock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event {
+    pub label: &'static str,
+    pub value: u64,
+}
+
+struct Ring<const N: usize> {
+    events: [Option<Event>; N],
+    /// Slot the next event is written to, which is also the oldest event once the ring is full
+    next: usize,
+}
+
+/// Fixed size log keeping the last N events, for cheap in-process tracing
+pub struct RingLog<const N: usize> {
+    /// Held only to copy one event in or out, so a spin lock beats sleeping
+    ring: SpinLock<Ring<N>>,
+}
+
+impl<const N: usize> RingLog<N> {
+    pub fn new() -> Self {
+        const { assert!(N > 0, "RingLog needs room for at least one event") };
+
+        Self {
+            ring: SpinLock::new(Ring {
+                events: [None; N],
+                next: 0,
+            }),
+        }
+    }
+
+    /// Records an event, overwriting the oldest one when full
+    pub fn push(&self, event: Event) {
+        let mut ring = self.ring.lock();
+        let next = ring.next;
+        ring.events[next] = Some(event);
+        ring.next = (next + 1) % N;
+    }
+
+    /// Events currently held, oldest first
+    pub fn snapshot(&self) -> Vec<Event> {
+        let ring = self.ring.lock();
+        let (newest, oldest) = ring.events.split_at(ring.next);
+        oldest.iter().chain(newest).flatten().copied().collect()
+    }
+}
+
+impl<const N: usize> Default for RingLog<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(value: u64) -> Event {
+        Event {
+            label: "test",
+            value,
+        }
+    }
+
+    #[test]
+    fn keeps_last_n() {
+        let log = RingLog::<4>::new();
+
+        log.push(event(0));
+        log.push(event(1));
+        assert_eq!(log.snapshot(), vec![event(0), event(1)]);
+
+        for i in 2..7 {
+            log.push(event(i));
+        }
+        assert_eq!(log.snapshot(), (3..7).map(event).collect::<Vec<_>>());
+    }
+}
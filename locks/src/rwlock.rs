@@ -13,12 +13,52 @@ pub struct RwLock<T> {
     ///     - N is even -> Lock has N/2 Readers
     ///     - N is odd -> Lock has writer(s) waiting + (N-1)/2 readers
     /// u32::MAX -> Lock is writer locked
+    /// Readers on the fast path can briefly wrap a write locked state around by 2 before backing out, see read
     state: AtomicU32,
     /// Value lock is holding
     value: UnsafeCell<T>,
     /// Atomic value for writers to listen on, increment to wake waiting writers
     /// Used to separately wake up writers, allowing us to avoid writer starvation
     writer_beacon: AtomicU32,
+    /// Reader count to alert at, and the callback to alert with, see RwLock::on_reader_limit
+    #[cfg(feature = "reader-limit-hook")]
+    reader_limit: Option<(u32, ReaderLimitHook)>,
+}
+
+#[cfg(feature = "reader-limit-hook")]
+type ReaderLimitHook = Box<dyn Fn() + Send + Sync>;
+
+/// Highest even state, i.e. the most readers the lock lets in at once
+/// One more would be u32::MAX - 1, next to the write locked encoding
+const READERS_FULL: u32 = u32::MAX - 3;
+
+/// Highest state read still takes the fetch_add fast path from, above it readers CAS in
+/// Leaves room for the adds that land between a reader's load and its own add, so they can't carry the
+/// count past READERS_FULL: that would take a billion read locks in that window
+const FAST_PATH_FULL: u32 = READERS_FULL / 2;
+
+/// RwLock::state, decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RwState {
+    Free,
+    /// Number of readers holding the lock
+    Read(u32),
+    /// Writer(s) waiting for these readers to leave
+    WritePending(u32),
+    WriteLocked,
+}
+
+impl RwState {
+    /// None for a value the lock can never hold: u32::MAX - 1 readers' worth, which would collide with WriteLocked
+    fn decode(s: u32) -> Option<Self> {
+        match s {
+            0 => Some(RwState::Free),
+            u32::MAX => Some(RwState::WriteLocked),
+            s if s == u32::MAX - 1 => None,
+            s if s.is_multiple_of(2) => Some(RwState::Read(s / 2)),
+            s => Some(RwState::WritePending((s - 1) / 2)),
+        }
+    }
 }
 
 /// Sync for RwLock because we want the rwlock to be shared amongst threads,
@@ -31,23 +71,57 @@ impl<T> RwLock<T> {
             state: AtomicU32::new(0),
             value: UnsafeCell::new(value),
             writer_beacon: AtomicU32::new(0),
+            #[cfg(feature = "reader-limit-hook")]
+            reader_limit: None,
         }
     }
-    fn read(&self) -> ReadGuard<T> {
+
+    /// Calls `hook` from the read that brings the reader count up to `high_water`,
+    /// so monitoring can alert well before the hard cap on readers is hit
+    /// Fires again every time the count climbs back up to it
+    #[cfg(feature = "reader-limit-hook")]
+    pub fn on_reader_limit(&mut self, high_water: u32, hook: ReaderLimitHook) {
+        self.reader_limit = Some((high_water, hook));
+    }
+
+    pub fn read(&self) -> ReadGuard<'_, T> {
+        // Fast path: optimistically count ourselves in, a single fetch_add instead of a CAS loop
+        // Only well below the reader cap, an add at the cap would step onto u32::MAX - 1, which isn't a valid state
+        let s = self.state.load(Ordering::Relaxed);
+        if s.is_multiple_of(2) && s <= FAST_PATH_FULL {
+            let s = self.state.fetch_add(2, Ordering::Acquire);
+            if s.is_multiple_of(2) && s < READERS_FULL {
+                return self.read_locked(s);
+            }
+
+            // A writer got in between the load and the add, back out and queue up behind it
+            // Backing out is a read unlock, so a waiting writer still gets woken if we were the last reader in
+            // On a write locked state the add wrapped around (u32::MAX + 2 = 1), write unlock is arithmetic so it composes
+            read_unlock(&self.state, &self.writer_beacon);
+        }
+        self.read_slow()
+    }
+
+    fn read_slow(&self) -> ReadGuard<'_, T> {
         // NOTE: If concerned that state may change between the load + processing operations as the function is not entirely atomic
         // CAS operation after the state.load() addresses the above concerns
         let mut s = self.state.load(Ordering::Acquire);
 
         loop {
             // u32::MAX is odd, so won't trigger here
-            if s % 2 == 0 {
-                assert_ne!(s, u32::MAX - 2, "Too many readers");
+            if s.is_multiple_of(2) {
+                // Too many readers: wait for one to leave instead of overflowing, the cap acts as backpressure
+                if s >= READERS_FULL {
+                    wait(&self.state, s);
+                    s = self.state.load(Ordering::Acquire);
+                    continue;
+                }
 
                 match self
                     .state
                     .compare_exchange(s, s + 2, Ordering::Acquire, Ordering::Relaxed)
                 {
-                    Ok(_) => return ReadGuard { lock: self },
+                    Ok(_) => return self.read_locked(s),
                     Err(e) => s = e,
                 }
             }
@@ -55,29 +129,149 @@ impl<T> RwLock<T> {
             // Captures the following cases:
             // 1. Currently write locked as u32::Max is odd,
             // 2. If there are any waiting writers
+            // Waits on the observed state, not u32::MAX: with writers pending and readers still in, the state is
+            // some other odd value, and waiting for MAX would return straight away and spin
+            // A pending writer always ends up write locking and unlocking, and write unlock wakes every reader
             if s % 2 == 1 {
-                wait(&self.state, u32::MAX);
+                wait(&self.state, s);
                 s = self.state.load(Ordering::Acquire);
             }
         }
     }
 
-    fn write(&mut self) -> WriteGuard<T> {
-        while let Err(e) =
-            self.state
-                .compare_exchange(0, u32::MAX, Ordering::Acquire, Ordering::Relaxed)
-        {
-            let writer_beacon = self.state.load(Ordering::Acquire);
-            if self.state.load(Ordering::Acquire) != 0 {
-                wait(&self.writer_beacon, writer_beacon)
+    /// A read lock was taken from state `s`
+    fn read_locked(&self, s: u32) -> ReadGuard<'_, T> {
+        self.check_state();
+        #[cfg(feature = "reader-limit-hook")]
+        if let Some((high_water, hook)) = &self.reader_limit {
+            if (s + 2) / 2 == *high_water {
+                hook()
             }
         }
+        #[cfg(not(feature = "reader-limit-hook"))]
+        let _ = s;
+
+        ReadGuard { lock: self }
+    }
 
+    /// Blocks until no reader or other writer holds the lock
+    /// Takes &self, so writers on different threads share the lock like readers do
+    ///
+    /// ```
+    /// use std::{sync::Arc, thread};
+    /// use locks::rwlock::RwLock;
+    ///
+    /// let lock = Arc::new(RwLock::new(0));
+    /// let writers: Vec<_> = (0..4)
+    ///     .map(|_| {
+    ///         let lock = Arc::clone(&lock);
+    ///         thread::spawn(move || *lock.write() += 1)
+    ///     })
+    ///     .collect();
+    /// for w in writers {
+    ///     w.join().unwrap();
+    /// }
+    /// assert_eq!(*lock.read(), 4);
+    /// ```
+    pub fn write(&self) -> WriteGuard<'_, T> {
+        // Whether this writer set the pending bit, only then may it take the lock from 1 (pending, no readers)
+        // Anyone else seeing 1 could be looking at a fast path reader's transient increment on a write locked state
+        let mut pending = false;
+        let mut s = self.state.load(Ordering::Relaxed);
+
+        loop {
+            let free = u32::from(pending);
+            if s == free {
+                match self
+                    .state
+                    .compare_exchange(s, u32::MAX, Ordering::Acquire, Ordering::Relaxed)
+                {
+                    Ok(_) => break,
+                    Err(e) => {
+                        s = e;
+                        continue;
+                    }
+                }
+            }
+
+            // Readers are in, set the pending bit so no new ones join and the last one out wakes us
+            if !pending && s.is_multiple_of(2) {
+                let prev = self.set_write_pending();
+                pending = prev.is_multiple_of(2);
+                s = prev | 1;
+                continue;
+            }
+
+            // Snapshot the beacon before re-checking the state, so a wake in between isn't missed
+            let writer_beacon = self.writer_beacon.load(Ordering::Acquire);
+            s = self.state.load(Ordering::Relaxed);
+            if s != free && (pending || !s.is_multiple_of(2)) {
+                wait(&self.writer_beacon, writer_beacon);
+                s = self.state.load(Ordering::Relaxed);
+            }
+        }
+
+        self.check_state();
         WriteGuard { lock: self }
     }
+
+    /// Read locks only if that's possible right now, with a single CAS that never waits
+    /// None while a writer holds or waits for the lock, or the reader cap is reached
+    pub fn try_read(&self) -> Option<ReadGuard<'_, T>> {
+        let s = self.state.load(Ordering::Relaxed);
+        if !s.is_multiple_of(2) || s >= READERS_FULL {
+            return None;
+        }
+        self.state
+            .compare_exchange(s, s + 2, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|s| self.read_locked(s))
+    }
+
+    /// Write locks only if the lock is free right now
+    /// Leaves the pending bit and the beacon alone, so failing doesn't hold readers back or wake anyone
+    pub fn try_write(&self) -> Option<WriteGuard<'_, T>> {
+        self.state
+            .compare_exchange(0, u32::MAX, Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+        self.check_state();
+        Some(WriteGuard { lock: self })
+    }
+
+    /// Replaces the value with `f` of it under the write lock, returning the new value
+    pub fn update<F: FnOnce(T) -> T>(&self, f: F) -> T
+    where
+        T: Copy,
+    {
+        let mut guard = self.write();
+        *guard = f(*guard);
+        *guard
+    }
+
+    /// Sets the writer pending bit in a single fetch_or, returning the previous state
+    /// Readers keep their count in the upper bits, so this never disturbs it. If the bit was already set
+    /// (another pending writer, or write locked) nothing changes, and the caller doesn't own the bit
+    fn set_write_pending(&self) -> u32 {
+        self.state.fetch_or(1, Ordering::Relaxed)
+    }
+
+    /// Decodes the state from a single load, only a snapshot for debugging
+    pub fn state_snapshot(&self) -> RwState {
+        let s = self.state.load(Ordering::Relaxed);
+        RwState::decode(s).unwrap_or_else(|| panic!("corrupt rwlock state: {s}"))
+    }
+
+    /// With the strict feature, debug builds check the state is a valid encoding after every operation
+    fn check_state(&self) {
+        #[cfg(feature = "strict")]
+        {
+            let s = self.state.load(Ordering::Relaxed);
+            debug_assert!(RwState::decode(s).is_some(), "corrupt rwlock state: {s}");
+        }
+    }
 }
 
-struct ReadGuard<'a, T> {
+pub struct ReadGuard<'a, T> {
     lock: &'a RwLock<T>,
 }
 
@@ -89,19 +283,71 @@ impl<'a, T> Deref for ReadGuard<'a, T> {
     }
 }
 
+impl<'a, T> ReadGuard<'a, T> {
+    /// Narrows the guard to two parts of the value, e.g. two fields, under the same read lock
+    pub fn map2<U, V, F>(guard: Self, f: F) -> MappedReadGuard2<'a, U, V>
+    where
+        F: FnOnce(&'a T) -> (&'a U, &'a V),
+    {
+        let lock = guard.lock;
+        // The mapped guard takes over releasing the read lock
+        std::mem::forget(guard);
+
+        MappedReadGuard2 {
+            parts: f(unsafe { &*lock.value.get() }),
+            state: &lock.state,
+            writer_beacon: &lock.writer_beacon,
+        }
+    }
+}
+
 impl<T> Drop for ReadGuard<'_, T> {
     fn drop(&mut self) {
+        read_unlock(&self.lock.state, &self.lock.writer_beacon);
+        self.lock.check_state();
+    }
+}
+
+fn read_unlock(state: &AtomicU32, writer_beacon: &AtomicU32) {
+    // Release, so the reads done under the lock happen before the next writer's changes
+    match state.fetch_sub(2, Ordering::Release) {
         // Decrementing from 3 -> 1, indicates there is a waiting writer
-        if self.lock.state.fetch_sub(2, Ordering::Acquire) == 3 {
-            // Wake writer
-            self.lock.writer_beacon.fetch_add(1, Ordering::Release);
-            wake_one(&self.lock.writer_beacon);
+        3 => {
+            // Wake writers, all of them: only the one that set the pending bit may take the lock from 1,
+            // and waking a single writer could pick another one, which just goes back to sleep
+            writer_beacon.fetch_add(1, Ordering::Release);
+            wake_all(writer_beacon);
         }
+        // Leaving a full lock, there's room for a reader waiting on the cap
+        READERS_FULL => wake_one(state),
+        _ => {}
+    }
+}
+
+/// ReadGuard::map2 -> MappedReadGuard2, derefs to both parts as a tuple
+/// Only keeps the lock's atomics, as the value's type is no longer known
+pub struct MappedReadGuard2<'a, U, V> {
+    parts: (&'a U, &'a V),
+    state: &'a AtomicU32,
+    writer_beacon: &'a AtomicU32,
+}
+
+impl<'a, U, V> Deref for MappedReadGuard2<'a, U, V> {
+    type Target = (&'a U, &'a V);
+
+    fn deref(&self) -> &Self::Target {
+        &self.parts
+    }
+}
+
+impl<U, V> Drop for MappedReadGuard2<'_, U, V> {
+    fn drop(&mut self) {
+        read_unlock(self.state, self.writer_beacon)
     }
 }
 
-struct WriteGuard<'a, T> {
-    lock: &'a mut RwLock<T>,
+pub struct WriteGuard<'a, T> {
+    lock: &'a RwLock<T>,
 }
 
 impl<'a, T> Deref for WriteGuard<'a, T> {
@@ -118,21 +364,46 @@ impl<'a, T> DerefMut for WriteGuard<'a, T> {
     }
 }
 
+impl<'a, T> WriteGuard<'a, T> {
+    /// Turns the write lock into a read lock without releasing it in between,
+    /// so the value built under the write lock is read as is, and other readers can join
+    pub fn downgrade(guard: Self) -> ReadGuard<'a, T> {
+        let lock = guard.lock;
+        // The read guard takes over unlocking
+        std::mem::forget(guard);
+
+        // u32::MAX -> 2, wrapping like the write unlock so fast path readers backing out still balance
+        lock.state.fetch_add(3, Ordering::Release);
+        // A writer that slept on the beacon never set the pending bit, it has to re-check and set it now
+        lock.writer_beacon.fetch_add(1, Ordering::Release);
+        wake_all(&lock.writer_beacon);
+        wake_all(&lock.state);
+        lock.check_state();
+
+        ReadGuard { lock }
+    }
+}
+
 impl<T> Drop for WriteGuard<'_, T> {
     fn drop(&mut self) {
-        // Free the lock
-        self.lock.state.store(0, Ordering::Release);
-        // First wake a potential waiting writer
+        // Free the lock, u32::MAX -> 0
+        // Subtracting instead of storing 0 keeps the increments of fast path readers backing out, see read
+        self.lock.state.fetch_sub(u32::MAX, Ordering::Release);
+        // First wake the waiting writers, all of them for the same reason as read_unlock
         self.lock.writer_beacon.fetch_add(1, Ordering::Release);
-        wake_one(&self.lock.writer_beacon);
+        wake_all(&self.lock.writer_beacon);
         // Then wake all waiting readers
         wake_all(&self.lock.state);
+        self.lock.check_state();
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::thread;
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
 
     use super::*;
 
@@ -147,4 +418,393 @@ mod tests {
 
     #[test]
     fn test() {}
+
+    #[test]
+    fn state_snapshot() {
+        let lock = RwLock::new(0);
+        assert_eq!(lock.state_snapshot(), RwState::Free);
+
+        let a = lock.read();
+        let b = lock.read();
+        assert_eq!(lock.state_snapshot(), RwState::Read(2));
+
+        // Whitebox: what a writer blocked behind the readers leaves behind
+        lock.state.fetch_add(1, Ordering::Relaxed);
+        assert_eq!(lock.state_snapshot(), RwState::WritePending(2));
+        lock.state.fetch_sub(1, Ordering::Relaxed);
+
+        drop((a, b));
+        assert_eq!(lock.state_snapshot(), RwState::Free);
+
+        let w = lock.write();
+        assert_eq!(lock.state_snapshot(), RwState::WriteLocked);
+        drop(w);
+        assert_eq!(lock.state_snapshot(), RwState::Free);
+    }
+
+    #[test]
+    #[cfg(all(feature = "strict", debug_assertions))]
+    #[should_panic(expected = "corrupt rwlock state")]
+    fn strict_catches_corrupt_state() {
+        let lock = RwLock::new(0);
+        lock.state.store(u32::MAX - 1, Ordering::Relaxed);
+        lock.check_state();
+    }
+
+    #[test]
+    #[cfg(feature = "reader-limit-hook")]
+    fn reader_limit_hook() {
+        use std::sync::{atomic::AtomicUsize, Arc};
+
+        let alerts = Arc::new(AtomicUsize::new(0));
+        let mut lock = RwLock::new(0);
+        let counter = Arc::clone(&alerts);
+        lock.on_reader_limit(
+            3,
+            Box::new(move || {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }),
+        );
+
+        let mut guards = vec![lock.read(), lock.read()];
+        assert_eq!(alerts.load(Ordering::Relaxed), 0);
+
+        // Third reader crosses the threshold, the fourth is already past it
+        guards.push(lock.read());
+        assert_eq!(alerts.load(Ordering::Relaxed), 1);
+        guards.push(lock.read());
+        assert_eq!(alerts.load(Ordering::Relaxed), 1);
+
+        // Dropping back below and climbing up again alerts again
+        guards.truncate(2);
+        guards.push(lock.read());
+        assert_eq!(alerts.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn mixed_contention() {
+        let lock = RwLock::new((0u64, 0u64));
+
+        thread::scope(|s| {
+            for _ in 0..2 {
+                s.spawn(|| {
+                    for _ in 0..2000 {
+                        let mut pair = lock.write();
+                        pair.0 += 1;
+                        pair.1 += 1;
+                    }
+                });
+            }
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..2000 {
+                        // Never sees a writer halfway through
+                        let pair = lock.read();
+                        assert_eq!(pair.0, pair.1);
+                    }
+                });
+            }
+        });
+
+        assert_eq!(*lock.read(), (4000, 4000));
+        assert_eq!(lock.state_snapshot(), RwState::Free);
+    }
+
+    #[test]
+    fn writers_hand_over_pending_bit() {
+        use std::sync::mpsc;
+
+        // Several writers queue behind readers at once, only the one that set the pending bit can take the lock
+        // from it, so waking just one writer could pick one that goes straight back to sleep
+        let lock: &'static _ = Box::leak(Box::new(RwLock::new(0u64)));
+        let (done, finished) = mpsc::channel();
+
+        for i in 0..6 {
+            let done = done.clone();
+            thread::spawn(move || {
+                for j in 0..500 {
+                    if i < 3 {
+                        *lock.write() += 1;
+                    } else {
+                        // Hold on now and then, so writers pile up behind the readers
+                        let _guard = lock.read();
+                        if j % 4 == 0 {
+                            thread::sleep(Duration::from_micros(20));
+                        }
+                    }
+                }
+                done.send(()).unwrap();
+            });
+        }
+
+        for _ in 0..6 {
+            finished
+                .recv_timeout(Duration::from_secs(20))
+                .expect("rwlock stopped making progress");
+        }
+        assert_eq!(*lock.read(), 1500);
+        assert_eq!(lock.state_snapshot(), RwState::Free);
+    }
+
+    #[test]
+    fn non_owner_writer_woken_first() {
+        let lock: &'static _ = Box::leak(Box::new(RwLock::new(0)));
+        let reader = lock.read();
+
+        // W1 sets the pending bit and sleeps, then W2 queues behind it without owning the bit
+        let w1 = thread::spawn(|| *lock.write() += 1);
+        while lock.state_snapshot() != RwState::WritePending(1) {
+            thread::yield_now();
+        }
+        thread::sleep(Duration::from_millis(50));
+        let w2 = thread::spawn(|| *lock.write() += 1);
+        thread::sleep(Duration::from_millis(50));
+
+        // Whitebox: a stray wake up on the beacon re-queues W1 behind W2
+        lock.writer_beacon.fetch_add(1, Ordering::Release);
+        wake_one(&lock.writer_beacon);
+        thread::sleep(Duration::from_millis(50));
+
+        // The last reader out has to reach W1, not just whichever writer is first in line
+        drop(reader);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !(w1.is_finished() && w2.is_finished()) {
+            assert!(Instant::now() < deadline, "pending writer never woke up");
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(*lock.read(), 2);
+    }
+
+    #[test]
+    fn reader_cap_blocks_instead_of_aborting() {
+        let lock = RwLock::new(0);
+        // Whitebox: as if the lock was already full of readers
+        lock.state.store(READERS_FULL, Ordering::Relaxed);
+
+        thread::scope(|s| {
+            let reader = s.spawn(|| *lock.read());
+
+            thread::sleep(Duration::from_millis(100));
+            assert!(!reader.is_finished());
+
+            // One of the readers leaves
+            read_unlock(&lock.state, &lock.writer_beacon);
+            assert_eq!(reader.join().unwrap(), 0);
+        });
+
+        assert_eq!(lock.state.load(Ordering::Relaxed), READERS_FULL - 2);
+    }
+
+    #[test]
+    fn read_past_fast_path_limit() {
+        let lock = RwLock::new(0);
+        // Whitebox: as if the lock already had that many readers, read has to CAS in
+        lock.state.store(FAST_PATH_FULL + 2, Ordering::Relaxed);
+
+        let guard = lock.read();
+        assert_eq!(lock.state_snapshot(), RwState::Read(FAST_PATH_FULL / 2 + 2));
+        drop(guard);
+        assert_eq!(lock.state_snapshot(), RwState::Read(FAST_PATH_FULL / 2 + 1));
+    }
+
+    /// Takes the lock up to the cap for real, slow: about 2^31 read locks
+    #[test]
+    #[ignore]
+    fn reader_cap_stress() {
+        let lock = RwLock::new(0);
+        for _ in 0..READERS_FULL / 2 {
+            std::mem::forget(lock.read());
+        }
+        assert_eq!(lock.state_snapshot(), RwState::Read(READERS_FULL / 2));
+
+        thread::scope(|s| {
+            let reader = s.spawn(|| drop(lock.read()));
+            thread::sleep(Duration::from_millis(100));
+            assert!(!reader.is_finished());
+
+            read_unlock(&lock.state, &lock.writer_beacon);
+            reader.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn pending_writer_blocks_new_readers() {
+        let lock = RwLock::new(0);
+        let first = lock.read();
+
+        thread::scope(|s| {
+            let writer = s.spawn(|| *lock.write() += 1);
+            while lock.state_snapshot() != RwState::WritePending(1) {
+                thread::yield_now();
+            }
+
+            // The intent bit is set, a new reader has to queue behind the writer
+            let reader = s.spawn(|| *lock.read());
+            thread::sleep(Duration::from_millis(100));
+            assert!(!reader.is_finished());
+            assert!(!writer.is_finished());
+
+            drop(first);
+            writer.join().unwrap();
+            // Only got in after the writer
+            assert_eq!(reader.join().unwrap(), 1);
+        });
+    }
+
+    #[test]
+    fn map2_fields() {
+        let lock = RwLock::new(("name".to_string(), vec![1u8, 2, 3]));
+
+        let mapped = ReadGuard::map2(lock.read(), |(name, bytes)| (name, bytes));
+        let (name, bytes) = *mapped;
+
+        // Other readers still get in while the mapped guard is held
+        thread::scope(|s| {
+            s.spawn(|| assert_eq!(lock.read().0, "name"));
+        });
+
+        assert_eq!(name, "name");
+        assert_eq!(bytes, &[1, 2, 3]);
+
+        drop(mapped);
+        assert_eq!(lock.state.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn writers_from_threads() {
+        let lock: &'static _ = Box::leak(Box::new(RwLock::new(0u64)));
+
+        let writers: Vec<_> = (0..8)
+            .map(|_| {
+                thread::spawn(|| {
+                    for _ in 0..1000 {
+                        *lock.write() += 1;
+                    }
+                })
+            })
+            .collect();
+        for w in writers {
+            w.join().unwrap();
+        }
+
+        assert_eq!(*lock.read(), 8000);
+        assert_eq!(lock.state_snapshot(), RwState::Free);
+    }
+
+    #[test]
+    fn update_from_threads() {
+        let lock = RwLock::new(0i32);
+
+        thread::scope(|s| {
+            for t in 1..=4 {
+                let lock = &lock;
+                s.spawn(move || {
+                    for _ in 0..1000 {
+                        lock.update(|v| v + t);
+                    }
+                });
+            }
+        });
+
+        assert_eq!(lock.update(|v| v), 10_000);
+        assert_eq!(*lock.read(), 10_000);
+    }
+
+    #[test]
+    fn try_read_try_write() {
+        let lock = RwLock::new(0);
+
+        let read = lock.read();
+        assert!(lock.try_write().is_none());
+        let second = lock.try_read().unwrap();
+        // The failed try_write didn't mark a writer as pending
+        assert_eq!(lock.state_snapshot(), RwState::Read(2));
+        drop((read, second));
+
+        let mut write = lock.try_write().unwrap();
+        *write += 1;
+        assert!(lock.try_read().is_none());
+        assert!(lock.try_write().is_none());
+        assert_eq!(lock.writer_beacon.load(Ordering::Relaxed), 0);
+        drop(write);
+
+        assert_eq!(*lock.try_read().unwrap(), 1);
+        assert_eq!(lock.state_snapshot(), RwState::Free);
+    }
+
+    #[test]
+    fn downgrade_lets_readers_in() {
+        let lock = RwLock::new(Vec::new());
+
+        let mut write = lock.write();
+        write.push(1);
+        let read = WriteGuard::downgrade(write);
+        assert_eq!(lock.state_snapshot(), RwState::Read(1));
+
+        thread::scope(|s| {
+            s.spawn(|| assert_eq!(*lock.read(), [1]));
+        });
+        assert_eq!(*read, [1]);
+
+        // A writer queued behind the downgraded guard gets in once it's dropped
+        thread::scope(|s| {
+            let writer = s.spawn(|| lock.write().push(2));
+            thread::sleep(Duration::from_millis(50));
+            drop(read);
+            writer.join().unwrap();
+        });
+        assert_eq!(*lock.read(), [1, 2]);
+        assert_eq!(lock.state_snapshot(), RwState::Free);
+    }
+
+    #[test]
+    fn no_thread_starves() {
+        use std::sync::atomic::AtomicUsize;
+
+        const READERS: usize = 4;
+        const WRITERS: usize = 2;
+        const ROUNDS: usize = 50;
+        const TOTAL: usize = (READERS + WRITERS) * ROUNDS;
+
+        let lock = RwLock::new(0);
+        // Acquisitions by every thread so far, a thread's wait is how many others got in while it queued
+        let served = AtomicUsize::new(0);
+
+        let longest_waits: Vec<usize> = thread::scope(|s| {
+            let handles: Vec<_> = (0..READERS + WRITERS)
+                .map(|i| {
+                    let (lock, served) = (&lock, &served);
+                    s.spawn(move || {
+                        let mut longest = 0;
+                        for _ in 0..ROUNDS {
+                            let start = served.load(Ordering::SeqCst);
+                            if i < READERS {
+                                let _guard = lock.read();
+                                longest =
+                                    longest.max(served.fetch_add(1, Ordering::SeqCst) - start);
+                                thread::sleep(Duration::from_micros(50));
+                            } else {
+                                *lock.write() += 1;
+                                longest =
+                                    longest.max(served.fetch_add(1, Ordering::SeqCst) - start);
+                            }
+                            // Bursts: every thread comes back for the lock shortly after leaving it
+                            thread::sleep(Duration::from_micros(100));
+                        }
+                        longest
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        assert_eq!(*lock.read(), WRITERS * ROUNDS);
+        assert_eq!(lock.state_snapshot(), RwState::Free);
+        for (i, longest) in longest_waits.iter().enumerate() {
+            assert!(
+                *longest < TOTAL / 4,
+                "thread {i} waited out {longest} acquisitions"
+            );
+        }
+    }
 }
@@ -0,0 +1,100 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use atomic_wait::{wait, wake_one};
+
+/// Counting semaphore, hands out up to `permits` concurrent acquisitions
+pub struct Semaphore {
+    /// Permits currently available, never goes below zero
+    permits: AtomicU32,
+}
+
+impl Semaphore {
+    pub const fn new(permits: u32) -> Self {
+        Self {
+            permits: AtomicU32::new(permits),
+        }
+    }
+
+    /// Blocks until a permit is available and takes it
+    pub fn acquire(&self) {
+        let mut p = self.permits.load(Ordering::Relaxed);
+
+        loop {
+            if p == 0 {
+                // A release in between bumps the count, so this returns instead of missing it
+                wait(&self.permits, 0);
+                p = self.permits.load(Ordering::Relaxed);
+                continue;
+            }
+
+            match self
+                .permits
+                .compare_exchange_weak(p, p - 1, Ordering::Acquire, Ordering::Relaxed)
+            {
+                Ok(_) => return,
+                Err(e) => p = e,
+            }
+        }
+    }
+
+    /// Takes a permit if one is available, without blocking
+    pub fn try_acquire(&self) -> bool {
+        let mut p = self.permits.load(Ordering::Relaxed);
+
+        while p > 0 {
+            match self
+                .permits
+                .compare_exchange_weak(p, p - 1, Ordering::Acquire, Ordering::Relaxed)
+            {
+                Ok(_) => return true,
+                Err(e) => p = e,
+            }
+        }
+        false
+    }
+
+    /// Returns a permit and wakes one thread blocked in acquire
+    pub fn release(&self) {
+        self.permits.fetch_add(1, Ordering::Release);
+        wake_one(&self.permits);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::atomic::AtomicUsize, thread, time::Duration};
+
+    use super::*;
+
+    #[test]
+    fn bounds_concurrency() {
+        let semaphore = Semaphore::new(3);
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            for _ in 0..20 {
+                s.spawn(|| {
+                    semaphore.acquire();
+                    let now = running.fetch_add(1, Ordering::Relaxed) + 1;
+                    peak.fetch_max(now, Ordering::Relaxed);
+                    thread::sleep(Duration::from_millis(5));
+                    running.fetch_sub(1, Ordering::Relaxed);
+                    semaphore.release();
+                });
+            }
+        });
+
+        assert!(peak.load(Ordering::Relaxed) <= 3);
+        assert_eq!(running.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn try_acquire_when_exhausted() {
+        let semaphore = Semaphore::new(1);
+        assert!(semaphore.try_acquire());
+        assert!(!semaphore.try_acquire());
+        semaphore.release();
+        assert!(semaphore.try_acquire());
+    }
+}
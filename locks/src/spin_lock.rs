@@ -2,63 +2,114 @@ use std::{
     cell::UnsafeCell,
     ops::{Deref, DerefMut},
     sync::atomic::{AtomicBool, Ordering},
+    thread,
 };
 
-pub struct SpinLock<T> {
+use crate::backoff::{BackoffPolicy, Spin};
+
+pub struct SpinLock<T, B = Spin> {
     lock: AtomicBool,
     data: UnsafeCell<T>,
+    /// Decides whether to keep spinning or yield to the scheduler on a contended lock
+    backoff: B,
 }
 
 // Impl sync for SpinLock where T is send
 // T impls send -> Can be safely sent to different threads
 // Implementing Sync -> Can safely be shared among threads
-unsafe impl<T> Sync for SpinLock<T> where T: Send {}
+unsafe impl<T, B> Sync for SpinLock<T, B>
+where
+    T: Send,
+    B: Sync,
+{
+}
 
 impl<T> SpinLock<T> {
     pub fn new(value: T) -> Self {
+        Self::with_backoff(value, Spin)
+    }
+}
+
+impl<T, B: BackoffPolicy> SpinLock<T, B> {
+    pub fn with_backoff(value: T, backoff: B) -> Self {
         Self {
             lock: AtomicBool::new(false),
             data: UnsafeCell::new(value),
+            backoff,
         }
     }
 
-    pub fn lock(&self) -> Guard<T> {
+    pub fn lock(&self) -> Guard<'_, T, B> {
+        let mut attempt = 0u32;
         while self.lock.swap(true, Ordering::Acquire) {
-            std::hint::spin_loop()
+            if !self.backoff.should_spin(attempt) {
+                thread::yield_now()
+            }
+            attempt = attempt.saturating_add(1);
         }
 
         Guard { lock: self }
     }
+}
+
+impl<T, B> SpinLock<T, B> {
+    /// Locks only if the lock is free right now, a single attempt that never spins
+    pub fn try_lock(&self) -> Option<Guard<'_, T, B>> {
+        self.lock
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+            .then(|| Guard { lock: self })
+    }
+
+    /// Tries to lock, retrying at most `max_spins` times before giving up
+    /// Never yields or blocks, so the worst case acquisition time is bounded
+    pub fn try_lock_spin(&self, max_spins: u32) -> Option<Guard<'_, T, B>> {
+        let mut spins = 0;
+        while self.lock.swap(true, Ordering::Acquire) {
+            if spins == max_spins {
+                return None;
+            }
+            spins += 1;
+            std::hint::spin_loop()
+        }
+
+        Some(Guard { lock: self })
+    }
 
     pub fn unlock(&self) {
         self.lock.store(false, Ordering::Release)
     }
 }
 
-pub struct Guard<'a, T> {
-    lock: &'a SpinLock<T>,
+/// Only Sync when T is, sharing the guard shares &T
+/// ```compile_fail,E0277
+/// use std::cell::Cell;
+/// use locks::spin_lock::Guard;
+///
+/// fn assert_sync<T: Sync>() {}
+/// assert_sync::<Guard<'static, Cell<u32>>>();
+/// ```
+pub struct Guard<'a, T, B = Spin> {
+    lock: &'a SpinLock<T, B>,
 }
 
-impl<T> Deref for Guard<'_, T>
-where
-    T: Send,
-{
+/// The T: Send bound on SpinLock's Sync would otherwise carry over through &SpinLock
+unsafe impl<T: Sync, B: Sync> Sync for Guard<'_, T, B> {}
+
+impl<T, B> Deref for Guard<'_, T, B> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
         unsafe { &*self.lock.data.get() }
     }
 }
 
-impl<T> DerefMut for Guard<'_, T>
-where
-    T: Send,
-{
+impl<T, B> DerefMut for Guard<'_, T, B> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         unsafe { &mut *self.lock.data.get() }
     }
 }
 
-impl<T> Drop for Guard<'_, T> {
+impl<T, B> Drop for Guard<'_, T, B> {
     fn drop(&mut self) {
         self.lock.lock.store(false, Ordering::Release)
     }
@@ -68,6 +119,7 @@ impl<T> Drop for Guard<'_, T> {
 mod tests {
 
     use super::*;
+    use crate::backoff::{ExponentialBackoff, NoSpin};
     use std::thread;
     #[test]
     fn to_10000() {
@@ -91,4 +143,72 @@ mod tests {
 
         assert_eq!(*spin_lock.lock(), 250);
     }
+
+    #[test]
+    fn try_lock_spin_gives_up() {
+        let spin_lock = SpinLock::new(0);
+
+        let guard = spin_lock.lock();
+        assert!(spin_lock.try_lock_spin(0).is_none());
+        assert!(spin_lock.try_lock_spin(10_000).is_none());
+        drop(guard);
+
+        let mut guard = spin_lock.try_lock_spin(0).unwrap();
+        *guard += 1;
+        drop(guard);
+        assert_eq!(*spin_lock.lock(), 1);
+    }
+
+    #[test]
+    fn try_lock_while_held() {
+        let spin_lock = SpinLock::new(0);
+
+        let guard = spin_lock.lock();
+        thread::scope(|s| {
+            s.spawn(|| assert!(spin_lock.try_lock().is_none()));
+        });
+        drop(guard);
+
+        *spin_lock.try_lock().unwrap() += 1;
+        assert_eq!(*spin_lock.lock(), 1);
+    }
+
+    /// A Sync but not Send value is still readable through the guard
+    #[test]
+    fn deref_without_send() {
+        struct NotSend(
+            u32,
+            std::marker::PhantomData<std::sync::MutexGuard<'static, ()>>,
+        );
+
+        let spin_lock = SpinLock::new(NotSend(7, std::marker::PhantomData));
+        assert_eq!(spin_lock.lock().0, 7);
+    }
+
+    fn count_with<B: BackoffPolicy + Sync + 'static>(backoff: B) {
+        let spin_lock: &'static _ = Box::leak(Box::new(SpinLock::with_backoff(0, backoff)));
+
+        let threads: Vec<_> = (0..10)
+            .map(|_| {
+                thread::spawn(|| {
+                    for _ in 0..1000 {
+                        *spin_lock.lock() += 1;
+                    }
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(*spin_lock.lock(), 10000);
+    }
+
+    #[test]
+    fn backoff_policies() {
+        count_with(Spin);
+        count_with(ExponentialBackoff::default());
+        count_with(NoSpin);
+    }
 }
@@ -0,0 +1,32 @@
+use std::{sync::atomic::AtomicU32, time::Duration};
+
+/// atomic_wait::wait, but sleeps for at most `timeout`
+/// Can return early like wait does, so callers re-check their condition and the time left
+#[cfg(target_os = "linux")]
+pub fn wait_timeout(a: &AtomicU32, expected: u32, timeout: Duration) {
+    let ts = libc::timespec {
+        tv_sec: timeout.as_secs().min(libc::time_t::MAX as u64) as libc::time_t,
+        tv_nsec: timeout.subsec_nanos() as _,
+    };
+
+    // Same futex word and flags atomic_wait uses, so its wake_one/wake_all reach us
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            a,
+            libc::FUTEX_WAIT | libc::FUTEX_PRIVATE_FLAG,
+            expected,
+            &ts as *const libc::timespec,
+        );
+    };
+}
+
+/// No timed wait is exposed by atomic_wait on other platforms, nap in short slices instead
+#[cfg(not(target_os = "linux"))]
+pub fn wait_timeout(a: &AtomicU32, expected: u32, timeout: Duration) {
+    use std::sync::atomic::Ordering;
+
+    if a.load(Ordering::Relaxed) == expected {
+        std::thread::sleep(timeout.min(Duration::from_millis(1)));
+    }
+}